@@ -0,0 +1,50 @@
+//! Times lookups in a map which has churned through millions of inserts,
+//! before and after `compact` rebuilds its table. Run with
+//! `cargo bench --bench compact`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use expiringmap::ExpiringMap;
+
+const INSERTS: u64 = 2_000_000;
+const LIVE: u64 = 1_000;
+const LOOKUPS: u64 = 5_000_000;
+const TTL: Duration = Duration::from_secs(30);
+
+/// how long looking up `key(i)` takes on average, in nanoseconds
+fn lookup_time(map: &ExpiringMap<u64, u64>, key: impl Fn(u64) -> u64) -> u128 {
+    let start = Instant::now();
+    for i in 0..LOOKUPS {
+        black_box(map.get(&key(i)));
+    }
+    start.elapsed().as_nanos() / u128::from(LOOKUPS)
+}
+
+/// print how long lookups of live and missing keys take
+fn report(label: &str, map: &ExpiringMap<u64, u64>) {
+    let hit = lookup_time(map, |i| INSERTS - 1 - i % LIVE);
+    let miss = lookup_time(map, |i| INSERTS + i);
+    println!(
+        "{label}: capacity {}, {hit}ns per hit, {miss}ns per miss",
+        map.capacity()
+    );
+}
+
+fn main() {
+    // keep a window of `LIVE` keys, removing the oldest as each new one goes
+    // in, so the table sees millions of inserts and removals
+    let mut map = ExpiringMap::new();
+    for i in 0..INSERTS {
+        map.insert(i, i, TTL);
+        if i >= LIVE {
+            map.remove(&(i - LIVE));
+        }
+    }
+    println!("{INSERTS} inserts, {} live entries", map.len());
+    report("before compact", &map);
+    map.compact();
+    report("after compact ", &map);
+}
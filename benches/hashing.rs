@@ -0,0 +1,85 @@
+//! Compares how often `get_or_insert_with` hashes a long `String` key with
+//! how often looking it up and then inserting it does, and how long each
+//! takes. Every upsert misses, since that is when looking up and then
+//! inserting hashes twice. Run with `cargo bench --bench hashing`.
+
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    hint::black_box,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use expiringmap::ExpiringMap;
+
+const KEYS: usize = 1_000;
+const ROUNDS: usize = 20;
+const KEY_LEN: usize = 4 * 1024;
+const TTL: Duration = Duration::from_secs(30);
+
+/// a [`RandomState`] which counts how many keys it hashes
+#[derive(Clone, Default)]
+struct CountingState {
+    inner: RandomState,
+    hashes: Rc<Cell<u64>>,
+}
+
+impl BuildHasher for CountingState {
+    type Hasher = <RandomState as BuildHasher>::Hasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        self.hashes.set(self.hashes.get() + 1);
+        self.inner.build_hasher()
+    }
+}
+
+fn keys() -> Vec<String> {
+    (0..KEYS).map(|i| format!("{i:0>KEY_LEN$}")).collect()
+}
+
+/// run `f` over every key `ROUNDS` times, on a fresh map each round which
+/// already has room for every key, returning how many hashes that took and
+/// how long
+fn run(
+    keys: &[String],
+    mut f: impl FnMut(&mut ExpiringMap<String, usize, CountingState>, &String),
+) -> (u64, Duration) {
+    let state = CountingState::default();
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let mut map = ExpiringMap::with_capacity_and_hasher(KEYS, state.clone());
+        let start = Instant::now();
+        for key in keys {
+            f(&mut map, key);
+        }
+        elapsed += start.elapsed();
+        black_box(&map);
+    }
+    (state.hashes.get(), elapsed)
+}
+
+fn main() {
+    let keys = keys();
+    let (entry_hashes, entry_time) = run(&keys, |map, key| {
+        *map.get_or_insert_with(key.clone(), TTL, || 0) += 1;
+    });
+    let (double_hashes, double_time) = run(&keys, |map, key| {
+        if let Some(count) = map.get_mut(key) {
+            *count += 1;
+        } else {
+            map.insert(key.clone(), 1, TTL);
+        }
+    });
+    let ops = KEYS * ROUNDS;
+    println!("{ops} upserts of {KEYS} keys, {KEY_LEN} bytes each");
+    println!(
+        "get_or_insert_with: {entry_hashes} hashes, {}ns per op",
+        entry_time.as_nanos() / ops as u128
+    );
+    println!(
+        "get_mut + insert:   {double_hashes} hashes, {}ns per op",
+        double_time.as_nanos() / ops as u128
+    );
+}
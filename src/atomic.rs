@@ -0,0 +1,114 @@
+//! [`AtomicU64`], or a stand-in for targets without 64-bit atomics, like
+//! `thumbv7m-none-eabi`. The stand-in guards a plain `u64` with a spinlock,
+//! which is only ever held for a single arithmetic operation.
+
+#[cfg(target_has_atomic = "64")]
+pub use core::sync::atomic::AtomicU64;
+
+#[cfg(not(target_has_atomic = "64"))]
+pub use self::fallback::AtomicU64;
+
+#[cfg(not(target_has_atomic = "64"))]
+mod fallback {
+    use core::{
+        cell::UnsafeCell,
+        fmt::{self, Debug, Formatter},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    /// A `u64` behind a spinlock, with the parts of the
+    /// [`AtomicU64`](core::sync::atomic::AtomicU64) API this crate uses. The
+    /// orderings are ignored, since taking the lock is always sequentially
+    /// consistent.
+    #[derive(Default)]
+    pub struct AtomicU64 {
+        locked: AtomicBool,
+        value: UnsafeCell<u64>,
+    }
+
+    // SAFETY: `value` is only accessed while `locked` is held, or through
+    // `&mut self`
+    unsafe impl Sync for AtomicU64 {}
+
+    impl AtomicU64 {
+        pub const fn new(value: u64) -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        /// run `f` on the value while holding the lock
+        fn with<T>(&self, f: impl FnOnce(&mut u64) -> T) -> T {
+            while self
+                .locked
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                core::hint::spin_loop();
+            }
+            // SAFETY: the lock is held, so nothing else can access the value
+            let result = f(unsafe { &mut *self.value.get() });
+            self.locked.store(false, Ordering::Release);
+            result
+        }
+
+        // not const, like `AtomicU64::get_mut`
+        #[allow(clippy::missing_const_for_fn)]
+        pub fn get_mut(&mut self) -> &mut u64 {
+            self.value.get_mut()
+        }
+
+        pub fn load(&self, _: Ordering) -> u64 {
+            self.with(|value| *value)
+        }
+
+        pub fn store(&self, new: u64, _: Ordering) {
+            self.with(|value| *value = new);
+        }
+
+        pub fn fetch_add(&self, by: u64, _: Ordering) -> u64 {
+            self.with(|value| core::mem::replace(value, value.wrapping_add(by)))
+        }
+
+        pub fn compare_exchange(
+            &self,
+            current: u64,
+            new: u64,
+            _: Ordering,
+            _: Ordering,
+        ) -> Result<u64, u64> {
+            self.with(|value| {
+                if *value == current {
+                    *value = new;
+                    Ok(current)
+                } else {
+                    Err(*value)
+                }
+            })
+        }
+
+        pub fn fetch_update(
+            &self,
+            _: Ordering,
+            _: Ordering,
+            mut f: impl FnMut(u64) -> Option<u64>,
+        ) -> Result<u64, u64> {
+            self.with(|value| {
+                let old = *value;
+                f(old)
+                    .map(|new| {
+                        *value = new;
+                        old
+                    })
+                    .ok_or(old)
+            })
+        }
+    }
+
+    impl Debug for AtomicU64 {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            Debug::fmt(&self.load(Ordering::Relaxed), f)
+        }
+    }
+}
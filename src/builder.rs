@@ -0,0 +1,195 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+
+use crate::{
+    collections::RandomState, trigger::Trigger, Clock, ExpiringMap, ShrinkPolicy, SystemClock,
+    VacuumTrigger,
+};
+
+/// A builder for an [`ExpiringMap`] with any of its settings changed from the
+/// defaults. [`ExpiringMap::new`] and [`ExpiringMap::with_capacity`] are
+/// shortcuts for the common cases.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use expiringmap::ExpiringMapBuilder;
+/// let mut map = ExpiringMapBuilder::new()
+///     .default_ttl(Duration::from_secs(60))
+///     .capacity(16)
+///     .max_len(100)
+///     .build();
+/// map.insert_default("key", "value");
+/// assert_eq!(map.get(&"key"), Some(&"value"));
+/// ```
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ExpiringMapBuilder<S = RandomState, C = SystemClock> {
+    default_ttl: Duration,
+    capacity: usize,
+    vacuum_factor: Option<f32>,
+    min_vacuum_size: Option<usize>,
+    max_len: Option<usize>,
+    shrink_policy: ShrinkPolicy,
+    vacuum_on_read: bool,
+    stats: bool,
+    trigger: Option<Trigger>,
+    hasher: S,
+    clock: C,
+}
+
+impl ExpiringMapBuilder {
+    /// Create a builder with the default settings
+    pub fn new() -> Self {
+        Self {
+            default_ttl: Duration::ZERO,
+            capacity: 0,
+            vacuum_factor: None,
+            min_vacuum_size: None,
+            max_len: None,
+            shrink_policy: ShrinkPolicy::Never,
+            vacuum_on_read: false,
+            stats: false,
+            trigger: None,
+            hasher: RandomState::default(),
+            clock: SystemClock,
+        }
+    }
+}
+
+impl Default for ExpiringMapBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, C> ExpiringMapBuilder<S, C> {
+    /// The TTL used by [`ExpiringMap::insert_default`]
+    pub const fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// How many entries the map can hold before it reallocates
+    pub const fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// See [`ExpiringMap::set_vacuum_factor`]
+    ///
+    /// # Panics
+    /// If `factor` is not greater than 1.0
+    pub fn vacuum_factor(mut self, factor: f32) -> Self {
+        assert!(factor > 1.0, "vacuum factor must be greater than 1.0");
+        self.vacuum_factor = Some(factor);
+        self
+    }
+
+    /// See [`ExpiringMap::set_minimum_vacuum_size`]
+    pub const fn min_vacuum_size(mut self, size: usize) -> Self {
+        self.min_vacuum_size = Some(size);
+        self
+    }
+
+    /// See [`ExpiringMap::with_max_len`]
+    ///
+    /// # Panics
+    /// If `max` is zero
+    pub const fn max_len(mut self, max: usize) -> Self {
+        assert!(max > 0, "max len must be greater than 0");
+        self.max_len = Some(max);
+        self
+    }
+
+    /// See [`ExpiringMap::set_shrink_policy`]
+    pub const fn shrink_policy(mut self, policy: ShrinkPolicy) -> Self {
+        self.shrink_policy = policy;
+        self
+    }
+
+    /// Shrink to fit after a vacuum leaves fewer than `percent` of the map's
+    /// capacity in use. This is the same as
+    /// [`ShrinkPolicy::Below`](crate::ShrinkPolicy::Below), and is off by
+    /// default.
+    pub const fn shrink_after_vacuum(self, percent: u8) -> Self {
+        self.shrink_policy(ShrinkPolicy::Below(percent))
+    }
+
+    /// See [`ExpiringMap::set_vacuum_on_read`]
+    pub const fn vacuum_on_read(mut self, enabled: bool) -> Self {
+        self.vacuum_on_read = enabled;
+        self
+    }
+
+    /// Whether to [count reads and evictions](ExpiringMap::enable_stats).
+    /// This is off by default.
+    pub const fn stats(mut self, enabled: bool) -> Self {
+        self.stats = enabled;
+        self
+    }
+
+    /// See [`ExpiringMap::set_vacuum_trigger`]
+    pub fn vacuum_trigger<T>(mut self, trigger: T) -> Self
+    where
+        T: VacuumTrigger + Send + Sync + 'static,
+    {
+        self.trigger = Some(Trigger(Arc::new(trigger)));
+        self
+    }
+
+    /// Use `hasher` to hash keys
+    pub fn hasher<T>(self, hasher: T) -> ExpiringMapBuilder<T, C> {
+        ExpiringMapBuilder {
+            default_ttl: self.default_ttl,
+            capacity: self.capacity,
+            vacuum_factor: self.vacuum_factor,
+            min_vacuum_size: self.min_vacuum_size,
+            max_len: self.max_len,
+            shrink_policy: self.shrink_policy,
+            vacuum_on_read: self.vacuum_on_read,
+            stats: self.stats,
+            trigger: self.trigger,
+            hasher,
+            clock: self.clock,
+        }
+    }
+
+    /// Use `clock` to tell the time
+    pub fn clock<T: Clock>(self, clock: T) -> ExpiringMapBuilder<S, T> {
+        ExpiringMapBuilder {
+            default_ttl: self.default_ttl,
+            capacity: self.capacity,
+            vacuum_factor: self.vacuum_factor,
+            min_vacuum_size: self.min_vacuum_size,
+            max_len: self.max_len,
+            shrink_policy: self.shrink_policy,
+            vacuum_on_read: self.vacuum_on_read,
+            stats: self.stats,
+            trigger: self.trigger,
+            hasher: self.hasher,
+            clock,
+        }
+    }
+
+    /// Create the [`ExpiringMap`]
+    pub fn build<K, V>(self) -> ExpiringMap<K, V, S, C> {
+        let mut map =
+            ExpiringMap::with_capacity_hasher_and_clock(self.capacity, self.hasher, self.clock);
+        map.default_ttl = self.default_ttl;
+        map.max_len = self.max_len;
+        map.shrink_policy = self.shrink_policy;
+        map.vacuum_on_read = self.vacuum_on_read;
+        if self.stats {
+            map.stats.enable();
+        }
+        map.trigger = self.trigger;
+        if let Some(factor) = self.vacuum_factor {
+            map.vacuum_factor = factor;
+        }
+        if let Some(size) = self.min_vacuum_size {
+            map.min_vacuum_size = size;
+            map.last_size = size;
+        }
+        map
+    }
+}
@@ -0,0 +1,157 @@
+#[cfg(any(
+    test,
+    feature = "test-util",
+    all(feature = "std", not(feature = "wasm"))
+))]
+use alloc::sync::Arc;
+#[cfg(any(
+    test,
+    feature = "test-util",
+    all(feature = "std", not(feature = "wasm"))
+))]
+use core::{sync::atomic::Ordering, time::Duration};
+
+#[cfg(any(
+    test,
+    feature = "test-util",
+    all(feature = "std", not(feature = "wasm"))
+))]
+use crate::atomic::AtomicU64;
+use crate::time::Instant;
+
+/// A source of the current time for an [`ExpiringMap`](crate::ExpiringMap).
+/// The time must never go backwards.
+pub trait Clock {
+    /// The current time
+    fn now(&self) -> Instant;
+}
+
+/// A [`Clock`] which reads the system's monotonic clock with [`Instant::now`].
+/// Without the `std` feature there is no system clock, so this is not a
+/// [`Clock`], and maps have to be given one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A [`Clock`] which only moves forward when it is [advanced](Self::advance).
+///
+/// This is for testing expiry without sleeping. Clones share the same time, so
+/// one can be given to a map and the other kept to control it.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: Instant,
+    /// nanoseconds this clock has been advanced past `start`
+    offset: Arc<AtomicU64>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockClock {
+    /// Create a new [`MockClock`], starting at the current system time, or
+    /// at zero without the `std` feature
+    pub fn new() -> Self {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        #[cfg(not(feature = "std"))]
+        let start = Instant::from_duration(Duration::ZERO);
+        Self {
+            start,
+            offset: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Move this clock, and all of its clones, forward by `by`
+    pub fn advance(&self, by: Duration) {
+        let by = u64::try_from(by.as_nanos()).unwrap_or(u64::MAX);
+        // the closure always returns `Some`, so this can't fail
+        let _ = self
+            .offset
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |offset| {
+                Some(offset.saturating_add(by))
+            });
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + Duration::from_nanos(self.offset.load(Ordering::Relaxed))
+    }
+}
+
+/// A [`Clock`] which trades precision for speed by only reading the system
+/// clock every `resolution`, on a background ticker thread.
+///
+/// Telling the time is a single atomic load of the ticker's last reading, so
+/// a busy map reads the system clock far less often, but expiry may be noticed
+/// up to `resolution` late. A resolution of zero reads the system clock every
+/// time, like [`SystemClock`]. Clones share the same time, and the ticker
+/// stops soon after every clone has been dropped.
+#[cfg(all(feature = "std", not(feature = "wasm")))]
+#[derive(Debug, Clone)]
+pub struct CoarseClock {
+    start: Instant,
+    /// nanoseconds after `start` as of the ticker's last reading, or [`None`]
+    /// if this clock is exact
+    offset: Option<Arc<AtomicU64>>,
+}
+
+#[cfg(all(feature = "std", not(feature = "wasm")))]
+impl CoarseClock {
+    /// Create a new [`CoarseClock`] which reads the system clock every
+    /// `resolution`. If the ticker thread can't be spawned, it reads the
+    /// system clock every time instead.
+    pub fn new(resolution: Duration) -> Self {
+        if resolution.is_zero() {
+            return Self {
+                start: Instant::now(),
+                offset: None,
+            };
+        }
+        Self::ticking(resolution, SystemClock)
+    }
+
+    /// a clock which reads `source` every `resolution` on a ticker thread
+    pub(crate) fn ticking<C: Clock + Send + 'static>(resolution: Duration, source: C) -> Self {
+        let start = source.now();
+        let offset = Arc::new(AtomicU64::new(0));
+        let ticker = Arc::downgrade(&offset);
+        let spawned = std::thread::Builder::new()
+            .name("expiringmap-clock".into())
+            .spawn(move || loop {
+                std::thread::sleep(resolution);
+                let Some(offset) = ticker.upgrade() else {
+                    break;
+                };
+                let elapsed = source.now().saturating_duration_since(start);
+                let elapsed = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+                offset.store(elapsed, Ordering::Relaxed);
+            });
+        Self {
+            start,
+            offset: spawned.is_ok().then_some(offset),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", not(feature = "wasm")))]
+impl Clock for CoarseClock {
+    fn now(&self) -> Instant {
+        self.offset.as_ref().map_or_else(Instant::now, |offset| {
+            self.start + Duration::from_nanos(offset.load(Ordering::Relaxed))
+        })
+    }
+}
@@ -0,0 +1,37 @@
+//! The [`HashMap`] type used throughout the crate. Without the `std` feature
+//! this comes from `hashbrown`, with its default hasher in place of
+//! [`RandomState`].
+
+#[cfg(feature = "std")]
+pub use std::collections::{
+    hash_map::{self, RandomState},
+    HashMap,
+};
+
+#[cfg(not(feature = "std"))]
+pub use hashbrown::{hash_map, DefaultHashBuilder as RandomState, HashMap};
+
+/// `hash_map`'s entry types for a map with the hasher `S`. `hashbrown`'s
+/// entries name their hasher but `std`'s don't, so with `std` it is ignored.
+#[cfg(feature = "std")]
+pub mod entry {
+    use super::hash_map;
+
+    pub trait Hasher<S> {
+        type Entry;
+    }
+
+    impl<T, S> Hasher<S> for T {
+        type Entry = T;
+    }
+
+    pub type Entry<'a, K, V, S> = <hash_map::Entry<'a, K, V> as Hasher<S>>::Entry;
+    pub type OccupiedEntry<'a, K, V, S> = <hash_map::OccupiedEntry<'a, K, V> as Hasher<S>>::Entry;
+    pub type VacantEntry<'a, K, V, S> = <hash_map::VacantEntry<'a, K, V> as Hasher<S>>::Entry;
+}
+
+/// `hash_map`'s entry types for a map with the hasher `S`
+#[cfg(not(feature = "std"))]
+pub mod entry {
+    pub use hashbrown::hash_map::{Entry, OccupiedEntry, VacantEntry};
+}
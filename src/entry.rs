@@ -0,0 +1,377 @@
+use core::{
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    hash::{BuildHasher, Hash},
+    time::Duration,
+};
+
+use crate::{
+    atomic::AtomicU64,
+    collections::{entry as raw, hash_map, RandomState},
+    index::ExpiryIndex,
+    time::Instant,
+    Clock, EvictionListener, ExpiringMap, ExpiryValue, SystemClock,
+};
+
+/// A view into a single entry in an [`ExpiringMap`], which may either be
+/// vacant or occupied. Expired entries are always vacant.
+///
+/// This is constructed with [`ExpiringMap::entry`].
+pub enum Entry<'a, K, V, S = RandomState, C = SystemClock> {
+    /// A live entry
+    Occupied(OccupiedEntry<'a, K, V, S, C>),
+    /// A missing or expired entry
+    Vacant(VacantEntry<'a, K, V, S, C>),
+}
+
+/// A view into an occupied entry which has not expired
+pub struct OccupiedEntry<'a, K, V, S = RandomState, C = SystemClock> {
+    inner: OccupiedInner<'a, K, V, S, C>,
+}
+
+/// A view into a vacant entry. It may still contain an expired value, which
+/// will be replaced on insertion.
+pub struct VacantEntry<'a, K, V, S = RandomState, C = SystemClock> {
+    inner: VacantInner<'a, K, V, S, C>,
+    ttl: Duration,
+}
+
+/// The error returned by [`ExpiringMap::try_insert`](crate::ExpiringMap::try_insert)
+/// when the key already has a live value
+#[derive(Debug)]
+pub struct OccupiedError<'a, V> {
+    /// The value already in the map
+    pub existing: &'a mut V,
+    /// The value which was not inserted
+    pub value: V,
+}
+
+impl<V: Debug> Display for OccupiedError<'_, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to insert {:?}, key already has live value {:?}",
+            self.value, self.existing
+        )
+    }
+}
+
+impl<V: Debug> Error for OccupiedError<'_, V> {}
+
+/// The parts of the map besides the inner [`HashMap`](std::collections::HashMap)
+/// that an entry holding onto its slot needs to keep up to date. Only maps
+/// without a weight limit hand these out, so there are no weights to track.
+#[derive(Debug)]
+pub struct EntryContext<'a, K, V> {
+    pub now: Instant,
+    pub sliding: bool,
+    pub accesses: Option<&'a AtomicU64>,
+    pub listener: Option<&'a mut EvictionListener<K, V>>,
+    pub index: Option<&'a mut ExpiryIndex<K>>,
+}
+
+/// Where an entry's value lives. Making room in a map with a
+/// [weight limit](ExpiringMap::with_max_weight), or a full
+/// [bounded](ExpiringMap::with_max_len) one, can evict other entries, which
+/// needs the whole map, so entries in those maps hold the map and look their
+/// key up again rather than holding onto its slot.
+enum OccupiedInner<'a, K, V, S, C> {
+    Slot(raw::OccupiedEntry<'a, K, ExpiryValue<V>, S>),
+    Map(&'a mut ExpiringMap<K, V, S, C>, K),
+}
+
+enum VacantInner<'a, K, V, S, C> {
+    Vacant(
+        raw::VacantEntry<'a, K, ExpiryValue<V>, S>,
+        EntryContext<'a, K, V>,
+    ),
+    Expired(
+        raw::OccupiedEntry<'a, K, ExpiryValue<V>, S>,
+        EntryContext<'a, K, V>,
+    ),
+    Map(&'a mut ExpiringMap<K, V, S, C>, K),
+}
+
+impl<K: Debug, V, S, C> Debug for Entry<'_, K, V, S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Occupied(entry) => f.debug_tuple("Occupied").field(entry).finish(),
+            Self::Vacant(entry) => f.debug_tuple("Vacant").field(entry).finish(),
+        }
+    }
+}
+
+impl<K: Debug, V, S, C> Debug for OccupiedEntry<'_, K, V, S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.key())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: Debug, V, S, C> Debug for VacantEntry<'_, K, V, S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VacantEntry")
+            .field("key", self.key())
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher, C: Clock> Entry<'a, K, V, S, C> {
+    /// an entry which holds onto `key`'s slot in the map, for maps which can't
+    /// need to evict anything to insert it
+    pub(crate) fn new(
+        inner: raw::Entry<'a, K, ExpiryValue<V>, S>,
+        ttl: Duration,
+        context: EntryContext<'a, K, V>,
+    ) -> Self {
+        let inner = match inner {
+            hash_map::Entry::Occupied(inner) if inner.get().not_expired_at(context.now) => {
+                #[cfg(feature = "access-count")]
+                inner.get().hit();
+                inner
+                    .get()
+                    .touch(context.now, context.sliding, context.accesses);
+                return Self::Occupied(OccupiedEntry {
+                    inner: OccupiedInner::Slot(inner),
+                });
+            }
+            hash_map::Entry::Occupied(inner) => VacantInner::Expired(inner, context),
+            hash_map::Entry::Vacant(inner) => VacantInner::Vacant(inner, context),
+        };
+        Self::Vacant(VacantEntry { inner, ttl })
+    }
+
+    /// an entry which holds the whole map, for maps which may need to evict
+    /// other entries to insert it
+    pub(crate) fn with_map(map: &'a mut ExpiringMap<K, V, S, C>, key: K, ttl: Duration) -> Self {
+        let now = map.now();
+        match map.inner.get(&key) {
+            Some(value) if value.not_expired_at(now) => {
+                map.touch(value, now);
+                Self::Occupied(OccupiedEntry {
+                    inner: OccupiedInner::Map(map, key),
+                })
+            }
+            _ => Self::Vacant(VacantEntry {
+                inner: VacantInner::Map(map, key),
+                ttl,
+            }),
+        }
+    }
+
+    /// Insert `default` if the entry is vacant, and return a mutable reference
+    /// to the value in the entry
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Insert the result of `default` if the entry is vacant, and return a
+    /// mutable reference to the value in the entry
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Insert [`V::default`](Default::default) if the entry is vacant, and
+    /// return a mutable reference to the value in the entry
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Run `f` on the value if the entry is occupied
+    #[must_use]
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Self::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Self::Occupied(entry)
+            }
+            Self::Vacant(entry) => Self::Vacant(entry),
+        }
+    }
+}
+
+impl<K, V, S, C> Entry<'_, K, V, S, C> {
+    /// The key of this entry
+    pub fn key(&self) -> &K {
+        match self {
+            Self::Occupied(entry) => entry.key(),
+            Self::Vacant(entry) => entry.key(),
+        }
+    }
+}
+
+impl<K, V, S, C> OccupiedEntry<'_, K, V, S, C> {
+    /// The key of this entry
+    pub fn key(&self) -> &K {
+        match &self.inner {
+            OccupiedInner::Slot(entry) => entry.key(),
+            OccupiedInner::Map(_, key) => key,
+        }
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher, C: Clock> OccupiedEntry<'a, K, V, S, C> {
+    /// The expiry data of this entry
+    pub fn get_meta(&self) -> &ExpiryValue<V> {
+        match &self.inner {
+            OccupiedInner::Slot(entry) => entry.get(),
+            OccupiedInner::Map(map, key) => &map.inner[key],
+        }
+    }
+
+    /// A reference to the value in this entry
+    pub fn get(&self) -> &V {
+        &self.get_meta().value
+    }
+
+    /// A mutable reference to the value in this entry
+    pub fn get_mut(&mut self) -> &mut V {
+        match &mut self.inner {
+            OccupiedInner::Slot(entry) => &mut entry.get_mut().value,
+            OccupiedInner::Map(map, key) => &mut occupied(map, key).value,
+        }
+    }
+
+    /// Convert this entry into a mutable reference to its value, with the
+    /// lifetime of the map
+    pub fn into_mut(self) -> &'a mut V {
+        match self.inner {
+            OccupiedInner::Slot(entry) => &mut entry.into_mut().value,
+            OccupiedInner::Map(map, key) => &mut occupied(map, &key).value,
+        }
+    }
+
+    /// Replace the value in this entry, keeping its TTL, and return the old
+    /// value. In a map with a [weight limit](ExpiringMap::with_max_weight),
+    /// other entries are evicted if the new value doesn't fit.
+    pub fn insert(&mut self, value: V) -> V {
+        match &mut self.inner {
+            OccupiedInner::Slot(entry) => core::mem::replace(&mut entry.get_mut().value, value),
+            OccupiedInner::Map(map, key) => {
+                let weight = map.weigher.map_or(0, |w| (w.weigh)(&value));
+                map.make_weight_room(key, weight);
+                let entry = occupied(map, key);
+                let old_weight = core::mem::replace(&mut entry.weight, weight);
+                let old = core::mem::replace(&mut entry.value, value);
+                map.weight = map.weight - old_weight + weight;
+                old
+            }
+        }
+    }
+
+    /// Remove this entry from the map, returning its value
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Remove this entry from the map, returning its key and value
+    pub fn remove_entry(self) -> (K, V) {
+        match self.inner {
+            OccupiedInner::Slot(entry) => {
+                let (key, value) = entry.remove_entry();
+                (key, value.value)
+            }
+            OccupiedInner::Map(map, key) => {
+                let (key, value) = map
+                    .inner
+                    .remove_entry(&key)
+                    .unwrap_or_else(|| unreachable!("occupied entries stay in the map"));
+                map.weight -= value.weight;
+                (key, value.value)
+            }
+        }
+    }
+}
+
+/// the value an [`OccupiedEntry`] holding `map` has for `key`
+fn occupied<'m, K: Eq + Hash, V, S: BuildHasher, C>(
+    map: &'m mut ExpiringMap<K, V, S, C>,
+    key: &K,
+) -> &'m mut ExpiryValue<V> {
+    map.inner
+        .get_mut(key)
+        .unwrap_or_else(|| unreachable!("occupied entries stay in the map"))
+}
+
+impl<K, V, S, C> VacantEntry<'_, K, V, S, C> {
+    /// The key of this entry
+    pub fn key(&self) -> &K {
+        match &self.inner {
+            VacantInner::Vacant(entry, _) => entry.key(),
+            VacantInner::Expired(entry, _) => entry.key(),
+            VacantInner::Map(_, key) => key,
+        }
+    }
+
+    /// The TTL a value inserted into this entry will get
+    pub const fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher, C: Clock> VacantEntry<'a, K, V, S, C> {
+    /// Insert a value into this entry with the TTL passed to
+    /// [`ExpiringMap::entry`], and return a mutable reference to it
+    pub fn insert(self, value: V) -> &'a mut V {
+        let ttl = self.ttl;
+        self.insert_with_ttl(value, ttl)
+    }
+
+    /// Insert a value into this entry with `ttl` in place of the one passed to
+    /// [`ExpiringMap::entry`], and return a mutable reference to it. In a map
+    /// with a [weight limit](ExpiringMap::with_max_weight) or a
+    /// [length limit](ExpiringMap::with_max_len), other entries are evicted if
+    /// it doesn't fit, like with [`ExpiringMap::insert`].
+    pub fn insert_with_ttl(self, value: V, ttl: Duration) -> &'a mut V {
+        let (slot, context) = match self.inner {
+            VacantInner::Vacant(vacant, context) => (Ok(vacant), context),
+            VacantInner::Expired(expired, context) => (Err(expired), context),
+            VacantInner::Map(map, key) => {
+                let now = map.now();
+                let entry = map.prepare_entry(&key, value, Some(ttl), now);
+                return match map.inner.entry(key) {
+                    hash_map::Entry::Occupied(mut expired) => {
+                        let old = expired.insert(entry);
+                        map.weight -= old.weight;
+                        if let Some(listener) = &mut map.on_evict {
+                            (listener.0)(expired.key(), old.value);
+                        }
+                        &mut expired.into_mut().value
+                    }
+                    hash_map::Entry::Vacant(vacant) => &mut vacant.insert(entry).value,
+                };
+            }
+        };
+        let mut entry = ExpiryValue::new(value, Some(ttl), context.now);
+        entry.touch(context.now, false, context.accesses);
+        match slot {
+            Ok(vacant) => {
+                if let Some(index) = context.index {
+                    index.push(vacant.key(), &mut entry);
+                }
+                &mut vacant.insert(entry).value
+            }
+            Err(mut expired) => {
+                if let Some(index) = context.index {
+                    index.push(expired.key(), &mut entry);
+                }
+                let old = expired.insert(entry);
+                if let Some(listener) = context.listener {
+                    (listener.0)(expired.key(), old.value);
+                }
+                &mut expired.into_mut().value
+            }
+        }
+    }
+}
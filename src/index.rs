@@ -0,0 +1,126 @@
+use alloc::{boxed::Box, collections::BinaryHeap};
+use core::{cmp::Ordering, time::Duration};
+
+use crate::{time::Instant, wheel::Wheel, ExpiryValue};
+
+/// A queue of expiry deadlines, so entries which are due can be found
+/// without scanning the whole map. This is either a min-heap, or a [`Wheel`].
+///
+/// Records are never updated in place. Each entry remembers the id of the
+/// record tracking it, so records for overwritten or removed entries are
+/// recognized as stale and skipped when they come up.
+#[derive(Debug, Clone)]
+pub struct ExpiryIndex<K> {
+    queue: Queue<K>,
+    /// keys are stored in both the map and the heap, but `K: Clone` is only
+    /// required to turn the index on, so it is captured here
+    clone_key: fn(&K) -> K,
+    next_id: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Queue<K> {
+    Heap(BinaryHeap<Deadline<K>>),
+    Wheel(Box<Wheel<K>>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Deadline<K> {
+    pub at: Instant,
+    pub id: u64,
+    pub key: K,
+}
+
+impl<K> ExpiryIndex<K> {
+    pub const fn new(clone_key: fn(&K) -> K) -> Self {
+        Self {
+            queue: Queue::Heap(BinaryHeap::new()),
+            clone_key,
+            // 0 is left for entries which aren't tracked
+            next_id: 1,
+        }
+    }
+
+    /// an index backed by a timing wheel with ticks of `resolution`, starting
+    /// at `start`
+    pub fn with_wheel(clone_key: fn(&K) -> K, start: Instant, resolution: Duration) -> Self {
+        Self {
+            queue: Queue::Wheel(Box::new(Wheel::new(start, resolution))),
+            clone_key,
+            next_id: 1,
+        }
+    }
+
+    /// start tracking `value`, which is stored under `key`
+    pub fn push<V>(&mut self, key: &K, value: &mut ExpiryValue<V>) {
+        let Some(at) = value.deadline() else {
+            value.index_id = 0;
+            return;
+        };
+        value.index_id = self.next_id;
+        self.repush(Deadline {
+            at,
+            id: self.next_id,
+            key: (self.clone_key)(key),
+        });
+        self.next_id += 1;
+    }
+
+    /// put a popped record back, because its entry's deadline moved
+    pub fn repush(&mut self, record: Deadline<K>) {
+        match &mut self.queue {
+            Queue::Heap(heap) => heap.push(record),
+            Queue::Wheel(wheel) => wheel.push(record),
+        }
+    }
+
+    /// remove and return a record if it is due as of `now`. The heap returns
+    /// the earliest first, and the wheel returns them in no particular order.
+    pub fn pop_due(&mut self, now: Instant) -> Option<Deadline<K>> {
+        match &mut self.queue {
+            Queue::Heap(heap) => {
+                if heap.peek()?.at > now {
+                    return None;
+                }
+                heap.pop()
+            }
+            Queue::Wheel(wheel) => wheel.pop_due(now),
+        }
+    }
+
+    /// drop every record `is_current` rejects
+    pub fn retain(&mut self, mut is_current: impl FnMut(&K, u64) -> bool) {
+        match &mut self.queue {
+            Queue::Heap(heap) => heap.retain(|record| is_current(&record.key, record.id)),
+            Queue::Wheel(wheel) => wheel.retain(is_current),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match &mut self.queue {
+            Queue::Heap(heap) => heap.clear(),
+            Queue::Wheel(wheel) => wheel.clear(),
+        }
+    }
+}
+
+impl<K> PartialEq for Deadline<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at && self.id == other.id
+    }
+}
+
+impl<K> Eq for Deadline<K> {}
+
+impl<K> PartialOrd for Deadline<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K> Ord for Deadline<K> {
+    // reversed, so the max-heap pops the earliest deadline first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.at.cmp(&self.at).then(other.id.cmp(&self.id))
+    }
+}
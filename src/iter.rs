@@ -0,0 +1,79 @@
+use crate::{collections::hash_map, time::Instant, EvictionListener, ExpiryValue};
+
+/// An owning iterator over the live entries of an
+/// [`ExpiringMap`](crate::ExpiringMap). Expired entries are skipped, and
+/// passed to the map's [eviction listener](crate::ExpiringMap::on_evict).
+///
+/// This is created by calling `into_iter` on the map.
+#[derive(Debug)]
+pub struct IntoIter<K, V> {
+    inner: hash_map::IntoIter<K, ExpiryValue<V>>,
+    listener: Option<EvictionListener<K, V>>,
+    now: Instant,
+}
+
+/// An iterator over the keys and values of the live entries of an
+/// [`ExpiringMap`](crate::ExpiringMap)
+///
+/// This is created by calling `into_iter` on a reference to the map. Use
+/// [`ExpiringMap::iter`](crate::ExpiringMap::iter) to get the expiry data too.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    inner: hash_map::Iter<'a, K, ExpiryValue<V>>,
+    now: Instant,
+}
+
+impl<K, V> IntoIter<K, V> {
+    pub(crate) const fn new(
+        inner: hash_map::IntoIter<K, ExpiryValue<V>>,
+        listener: Option<EvictionListener<K, V>>,
+        now: Instant,
+    ) -> Self {
+        Self {
+            inner,
+            listener,
+            now,
+        }
+    }
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    pub(crate) const fn new(inner: hash_map::Iter<'a, K, ExpiryValue<V>>, now: Instant) -> Self {
+        Self { inner, now }
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (key, value) in self.inner.by_ref() {
+            if value.not_expired_at(self.now) {
+                return Some((key, value.value));
+            }
+            if let Some(listener) = &mut self.listener {
+                (listener.0)(&key, value.value);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = self.now;
+        self.inner
+            .find(|(_, value)| value.not_expired_at(now))
+            .map(|(key, value)| (key, &value.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
@@ -1,5 +1,5 @@
 //! [`ExpiringMap`] is a wrapper around [`HashMap`] that allows the specification
-//! of TTLs on entries. It does not support iteration.
+//! of TTLs on entries. Iteration only yields entries which have not expired.
 //!
 //! ```rust
 //! use std::time::Duration;
@@ -9,27 +9,135 @@
 //! std::thread::sleep(Duration::from_millis(60));
 //! assert!(map.get(&"key").is_none());
 //! ```
+//!
+//! With the `serde` feature, [`ExpiringMap`], [`ExpiringSet`], and
+//! [`ExpiryValue`] can be serialized. Entries are stored with their remaining
+//! TTL, which restarts when they are deserialized.
+//!
+//! With the `wasm` feature, [`web_time`](https://docs.rs/web-time) is used in
+//! place of [`std::time::Instant`], so the crate works in the browser.
+//!
+//! With the `tokio` feature, `spawn_reaper` can vacuum a shared map in the
+//! background.
+//!
+//! With the `async` feature, `get_or_insert_with_async` can compute missing
+//! values with a future.
+//!
+//! With the `access-count` feature, each entry counts how many times it has
+//! been read, for `ExpiryValue::access_count` and `most_accessed`. This makes
+//! every entry 8 bytes bigger, so it is off by default.
+//!
+//! The `std` feature is on by default. Without it, the crate only needs
+//! `alloc`, and [`HashMap`] comes from [`hashbrown`](https://docs.rs/hashbrown),
+//! which must be turned on with the `hashbrown` feature. With `std` on, the
+//! `hashbrown` feature does nothing, and the standard library's [`HashMap`] is
+//! always used. Without `std`, there is no system clock, so maps must be
+//! created with a [`Clock`] which counts up from some starting point, using the
+//! crate's own `Instant::from_duration`.
+//! [`SyncExpiringMap`] and the `serde`, `tokio`, and `wasm` features all
+//! require `std`.
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(clippy::all, clippy::pedantic, clippy::cargo, clippy::nursery)]
 #![allow(clippy::must_use_candidate)]
 
-use std::{
+#[cfg(not(any(feature = "std", feature = "hashbrown")))]
+compile_error!("the `hashbrown` feature is required when the `std` feature is off");
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+#[cfg(feature = "access-count")]
+use core::cmp::Reverse;
+#[cfg(feature = "async")]
+use core::future::Future;
+use core::{
     borrow::Borrow,
-    collections::HashMap,
-    hash::Hash,
-    ops::{Deref, DerefMut},
-    time::{Duration, Instant},
+    fmt::{self, Debug, Formatter},
+    hash::{BuildHasher, Hash},
+    ops::{Deref, DerefMut, Index, IndexMut},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
 
-#[cfg(test)]
+#[cfg(all(feature = "std", not(feature = "wasm")))]
+pub use crate::clock::CoarseClock;
+#[cfg(any(test, feature = "test-util"))]
+pub use crate::clock::MockClock;
+#[cfg(feature = "tokio")]
+pub use crate::reaper::spawn_reaper;
+#[cfg(feature = "std")]
+pub use crate::sync::SyncExpiringMap;
+#[cfg(not(feature = "std"))]
+pub use crate::time::Instant;
+#[cfg(feature = "std")]
+use crate::time::Instant;
+use crate::{
+    atomic::AtomicU64,
+    collections::{hash_map, HashMap, RandomState},
+    entry::EntryContext,
+    index::{Deadline, ExpiryIndex},
+    rng::SplitMix64,
+    stats::Stats,
+    trigger::Trigger,
+    weight::Weigher,
+};
+pub use crate::{
+    builder::ExpiringMapBuilder,
+    clock::{Clock, SystemClock},
+    entry::{Entry, OccupiedEntry, OccupiedError, VacantEntry},
+    iter::{IntoIter, Iter},
+    stats::CacheStats,
+    trigger::{GrowthTrigger, TimeTrigger, VacuumTrigger},
+    weight::Weight,
+};
+
+mod atomic;
+mod builder;
+mod clock;
+mod collections;
+mod entry;
+mod index;
+mod iter;
+#[cfg(feature = "tokio")]
+mod reaper;
+mod rng;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod stats;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(all(test, feature = "std"))]
 mod test;
+mod time;
+mod trigger;
+mod weight;
+mod wheel;
 
-type ExpiringMapInner<K, V> = HashMap<K, ExpiryValue<V>>;
+type ExpiringMapInner<K, V, S> = HashMap<K, ExpiryValue<V>, S>;
 
 /// A struct to contain a value and its expiry information
-#[derive(Debug, Clone)]
+///
+/// Methods which tell the time themselves, like [`Self::remaining`] and
+/// [`Self::expired`], go by the system clock and don't know whether the map
+/// is [frozen](ExpiringMap::set_frozen), so they keep counting down while it
+/// is. Map methods like [`ExpiringMap::remaining`] account for freezing.
+#[derive(Debug)]
 pub struct ExpiryValue<T> {
     inserted: Instant,
-    ttl: Duration,
+    /// nanoseconds after `inserted` this entry was last refreshed through a
+    /// shared reference, for sliding expiration
+    refreshed: AtomicU64,
+    /// the map's access counter when this entry was last used, for LRU eviction
+    accessed: AtomicU64,
+    /// how many times this entry has been read through the map
+    #[cfg(feature = "access-count")]
+    hits: AtomicU64,
+    /// the id of the [`ExpiryIndex`] record tracking this entry
+    index_id: u64,
+    /// the weight of `value` when it was inserted, if the map has a weight limit
+    weight: usize,
+    /// [`None`] if this entry never expires
+    ttl: Option<Duration>,
     value: T,
 }
 
@@ -41,20 +149,119 @@ impl<T> Deref for ExpiryValue<T> {
     }
 }
 
+impl<T> DerefMut for ExpiryValue<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+impl<T: Clone> Clone for ExpiryValue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inserted: self.inserted,
+            refreshed: AtomicU64::new(self.refreshed.load(Ordering::Relaxed)),
+            accessed: AtomicU64::new(self.accessed.load(Ordering::Relaxed)),
+            #[cfg(feature = "access-count")]
+            hits: AtomicU64::new(self.hits.load(Ordering::Relaxed)),
+            index_id: self.index_id,
+            weight: self.weight,
+            ttl: self.ttl,
+            value: self.value.clone(),
+        }
+    }
+}
+
 impl<T> ExpiryValue<T> {
-    /// When this value was inserted
-    pub const fn inserted(&self) -> Instant {
+    const fn new(value: T, ttl: Option<Duration>, inserted: Instant) -> Self {
+        Self {
+            inserted,
+            refreshed: AtomicU64::new(0),
+            accessed: AtomicU64::new(0),
+            #[cfg(feature = "access-count")]
+            hits: AtomicU64::new(0),
+            index_id: 0,
+            weight: 0,
+            ttl,
+            value,
+        }
+    }
+
+    /// When this value was inserted, or last refreshed, according to the
+    /// map's [`Clock`]
+    pub fn inserted(&self) -> Instant {
+        let refreshed = Duration::from_nanos(self.refreshed.load(Ordering::Relaxed));
         self.inserted
+            .checked_add(refreshed)
+            .unwrap_or(self.inserted)
     }
 
-    /// How long this entry will live
+    /// How long this entry will live. This is [`Duration::MAX`] for
+    /// [persistent](Self::is_persistent) entries.
     pub const fn ttl(&self) -> Duration {
-        self.ttl
+        match self.ttl {
+            Some(ttl) => ttl,
+            None => Duration::MAX,
+        }
+    }
+
+    /// How many times this entry has been read through the map since it was
+    /// inserted, such as by [`ExpiringMap::get`] or [`ExpiringMap::get_mut`].
+    /// Peeks and iteration are not counted. This needs the `access-count`
+    /// feature.
+    #[cfg(feature = "access-count")]
+    pub fn access_count(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
     }
 
-    /// How long is left before this entry is deleted
+    /// If this entry never expires
+    pub const fn is_persistent(&self) -> bool {
+        self.ttl.is_none()
+    }
+
+    /// Replace how long this entry will live. The TTL still counts from when
+    /// the entry was [inserted](Self::inserted).
+    pub const fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = Some(ttl);
+    }
+
+    /// Restart this entry's TTL from now, according to the system clock rather
+    /// than the map's [`Clock`]. Use [`ExpiringMap::renew`] to go by the map's
+    /// clock.
+    #[cfg(feature = "std")]
+    pub fn reset_inserted(&mut self) {
+        self.reset(Instant::now());
+    }
+
+    /// How long is left before this entry is deleted, according to the system
+    /// clock rather than the map's [`Clock`], ignoring
+    /// [freezing](ExpiringMap::set_frozen). This is [`Duration::MAX`] for
+    /// [persistent](Self::is_persistent) entries.
+    #[cfg(feature = "std")]
     pub fn remaining(&self) -> Duration {
-        self.ttl.saturating_sub(self.inserted.elapsed())
+        self.remaining_at(Instant::now())
+    }
+
+    /// How long ago this entry was [inserted](Self::inserted), according to
+    /// the system clock rather than the map's [`Clock`]
+    #[cfg(feature = "std")]
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_at(Instant::now())
+    }
+
+    /// How much of this entry's TTL is [left](Self::remaining), from 1.0 when
+    /// it is inserted down to 0.0 when it expires, according to the system
+    /// clock rather than the map's [`Clock`]. This is 1.0 for
+    /// [persistent](Self::is_persistent) entries and those with a zero TTL.
+    #[cfg(feature = "std")]
+    pub fn fraction_remaining(&self) -> f64 {
+        self.fraction_remaining_at(Instant::now())
+    }
+
+    /// Like [`Self::remaining`], but [`None`] for
+    /// [persistent](Self::is_persistent) entries
+    #[cfg(feature = "std")]
+    pub fn remaining_opt(&self) -> Option<Duration> {
+        self.ttl.map(|_| self.remaining())
     }
 
     /// Take ownership of the internal value
@@ -62,46 +269,240 @@ impl<T> ExpiryValue<T> {
         self.value
     }
 
-    /// If this entry is expired and should be deleted
+    /// If this entry had expired as of `now`, which should come from the
+    /// map's [`Clock`]. Checking several entries against the same `now` gives
+    /// consistent answers, where [`Self::expired`] reads the clock each time.
+    pub fn expired_at(&self, now: Instant) -> bool {
+        !self.not_expired_at(now)
+    }
+
+    /// If this entry is expired and should be deleted, according to the system
+    /// clock rather than the map's [`Clock`], ignoring
+    /// [freezing](ExpiringMap::set_frozen)
+    #[cfg(feature = "std")]
     pub fn expired(&self) -> bool {
         self.remaining().is_zero()
     }
 
     /// if this entry has not expired, and should be kept
+    #[cfg(feature = "std")]
     pub fn not_expired(&self) -> bool {
         !self.expired()
     }
+
+    /// When this entry expires, or [`None`] if that is too far in the future
+    /// to be represented
+    fn deadline(&self) -> Option<Instant> {
+        self.inserted().checked_add(self.ttl?)
+    }
+
+    /// how long is left before this entry is deleted as of `now`
+    fn remaining_at(&self, now: Instant) -> Duration {
+        self.ttl.map_or(Duration::MAX, |ttl| {
+            ttl.saturating_sub(now.saturating_duration_since(self.inserted()))
+        })
+    }
+
+    /// how long ago this entry was inserted as of `now`
+    #[cfg(feature = "std")]
+    fn elapsed_at(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.inserted())
+    }
+
+    /// how much of this entry's TTL is left as of `now`
+    #[cfg(feature = "std")]
+    fn fraction_remaining_at(&self, now: Instant) -> f64 {
+        match self.ttl {
+            Some(ttl) if !ttl.is_zero() => {
+                (self.remaining_at(now).as_secs_f64() / ttl.as_secs_f64()).clamp(0.0, 1.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// if this entry has not expired as of `now`
+    fn not_expired_at(&self, now: Instant) -> bool {
+        self.ttl
+            .is_none_or(|ttl| now.duration_since(self.inserted()) < ttl)
+    }
+
+    /// record a read for sliding expiration, and for LRU eviction if the map's
+    /// access counter is given
+    fn touch(&self, now: Instant, sliding: bool, accesses: Option<&AtomicU64>) {
+        if sliding {
+            self.refresh(now);
+        }
+        if let Some(accesses) = accesses {
+            let tick = accesses.fetch_add(1, Ordering::Relaxed);
+            self.accessed.store(tick, Ordering::Relaxed);
+        }
+    }
+
+    /// count a read for [`Self::access_count`]
+    #[cfg(feature = "access-count")]
+    fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// restart this entry's TTL from `now` through a shared reference
+    fn refresh(&self, now: Instant) {
+        let since = now.saturating_duration_since(self.inserted).as_nanos();
+        self.refreshed
+            .store(u64::try_from(since).unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    /// restart this entry's TTL from `now`
+    fn reset(&mut self, now: Instant) {
+        self.inserted = now;
+        *self.refreshed.get_mut() = 0;
+    }
+
+    /// apply `f` to the value, keeping the expiry data but not the weight,
+    /// which belonged to the old value
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> ExpiryValue<U> {
+        ExpiryValue {
+            inserted: self.inserted,
+            refreshed: self.refreshed,
+            accessed: self.accessed,
+            #[cfg(feature = "access-count")]
+            hits: self.hits,
+            index_id: self.index_id,
+            weight: 0,
+            ttl: self.ttl,
+            value: f(self.value),
+        }
+    }
 }
 
 /// A wrapper around [`HashMap`] which adds TTLs
-#[derive(Debug)]
-pub struct ExpiringMap<K, V> {
+pub struct ExpiringMap<K, V, S = RandomState, C = SystemClock> {
     last_size: usize,
-    inner: ExpiringMapInner<K, V>,
+    /// how many times bigger than `last_size` the map can get before
+    /// [`ExpiringMap::vacuum_if_needed`] vacuums
+    vacuum_factor: f32,
+    /// the minimum size to set `last_size` to so we don't go bananas with vacuums
+    min_vacuum_size: usize,
+    /// replaces the `vacuum_factor` rule if set
+    trigger: Option<Trigger>,
+    shrink_policy: ShrinkPolicy,
+    /// if [`ExpiringMap::vacuum_if_needed`] also runs on mutable reads, and
+    /// shared reads which find expired entries set `vacuum_pending`
+    vacuum_on_read: bool,
+    /// set by a shared read which found an expired entry, so the next
+    /// [`ExpiringMap::vacuum_if_needed`] vacuums
+    vacuum_pending: AtomicBool,
+    default_ttl: Duration,
+    /// used by [`ExpiringMap::insert_policied`] in place of `default_ttl`
+    ttl_policy: Option<TtlPolicy<K, V>>,
+    sliding: bool,
+    max_len: Option<usize>,
+    on_evict: Option<EvictionListener<K, V>>,
+    index: Option<ExpiryIndex<K>>,
+    /// incremented on every access when `max_len` is set, to find the least
+    /// recently used entry
+    accesses: AtomicU64,
+    /// for [`ExpiringMap::insert_jittered`]
+    rng: SplitMix64,
+    weigher: Option<Weigher<V>>,
+    /// the total weight of every entry, expired or not
+    weight: usize,
+    stats: Stats,
+    /// when [`ExpiringMap::set_frozen`] stopped the map's time, if it is frozen
+    frozen_at: Option<Instant>,
+    clock: C,
+    inner: ExpiringMapInner<K, V, S>,
 }
 
-#[derive(Debug)]
+/// Clones keep the expiry times of the original entries, but not its
+/// [eviction listener](ExpiringMap::on_evict), which can't be cloned.
+impl<K: Clone, V: Clone, S: Clone, C: Clone> Clone for ExpiringMap<K, V, S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            last_size: self.last_size,
+            vacuum_factor: self.vacuum_factor,
+            min_vacuum_size: self.min_vacuum_size,
+            trigger: self.trigger.clone(),
+            shrink_policy: self.shrink_policy,
+            vacuum_on_read: self.vacuum_on_read,
+            vacuum_pending: AtomicBool::new(self.vacuum_pending.load(Ordering::Relaxed)),
+            default_ttl: self.default_ttl,
+            ttl_policy: self.ttl_policy.clone(),
+            sliding: self.sliding,
+            max_len: self.max_len,
+            on_evict: None,
+            index: self.index.clone(),
+            accesses: AtomicU64::new(self.accesses.load(Ordering::Relaxed)),
+            rng: self.rng.clone(),
+            weigher: self.weigher,
+            weight: self.weight,
+            stats: self.stats.clone(),
+            frozen_at: self.frozen_at,
+            clock: self.clock.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// When an [`ExpiringMap`] gives memory back after vacuuming, including the
+/// vacuums run by inserts. [`ExpiringMap::shrink_to_fit`] always shrinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShrinkPolicy {
+    /// Shrink to fit after every vacuum
+    Aggressive,
+    /// After a vacuum, shrink to twice the number of entries if fewer than a
+    /// quarter of the capacity is in use
+    Lazy,
+    /// After a vacuum, shrink to fit if fewer than this percentage of the
+    /// capacity is in use
+    Below(u8),
+    /// Never shrink automatically. This is the default.
+    #[default]
+    Never,
+}
+
+type EvictionFn<K, V> = dyn FnMut(&K, V) + Send + Sync;
+
+/// A callback run on entries as they leave an [`ExpiringMap`]
+struct EvictionListener<K, V>(Box<EvictionFn<K, V>>);
+
+impl<K, V> Debug for EvictionListener<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("EvictionListener")
+    }
+}
+
+type TtlFn<K, V> = dyn Fn(&K, &V) -> Duration + Send + Sync;
+
+/// Picks the TTL of entries inserted with [`ExpiringMap::insert_policied`].
+/// Clones of the map share it.
+struct TtlPolicy<K, V>(Arc<TtlFn<K, V>>);
+
+impl<K, V> Clone for TtlPolicy<K, V> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+#[derive(Clone)]
 /// A set version of [`ExpiringMap`]. Sets `V` to [`()`](https://doc.rust-lang.org/stable/std/primitive.unit.html)
-pub struct ExpiringSet<K>(ExpiringMap<K, ()>);
+pub struct ExpiringSet<K, S = RandomState, C = SystemClock>(ExpiringMap<K, (), S, C>);
 
-impl<K> Deref for ExpiringSet<K> {
-    type Target = ExpiringMap<K, ()>;
+impl<K, S, C> Deref for ExpiringSet<K, S, C> {
+    type Target = ExpiringMap<K, (), S, C>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-impl<K> DerefMut for ExpiringSet<K> {
+impl<K, S, C> DerefMut for ExpiringSet<K, S, C> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
 
+#[cfg(feature = "std")]
 impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V> {
-    /// the minimum size to set `last_size` to so we don't go bananas with vacuums
-    const MINIMUM_VACUUM_SIZE: usize = 8;
-
     /// Create a new [`ExpiringMap`]
     pub fn new() -> Self {
         Self::with_capacity(0)
@@ -109,88 +510,1508 @@ impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V> {
 
     /// Create a new [`ExpiringMap`] with the specified capacity
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+
+    /// Create a new [`ExpiringMap`] whose [`Self::insert_default`] uses `ttl`
+    pub fn with_default_ttl(ttl: Duration) -> Self {
+        let mut map = Self::new();
+        map.default_ttl = ttl;
+        map
+    }
+
+    /// Create a new [`ExpiringMap`] whose [`Self::insert_policied`] picks each
+    /// entry's TTL by calling `policy` with its key and value
+    pub fn with_ttl_policy<F>(policy: F) -> Self
+    where
+        F: Fn(&K, &V) -> Duration + Send + Sync + 'static,
+    {
+        let mut map = Self::new();
+        map.set_ttl_policy(policy);
+        map
+    }
+
+    /// Create a new [`ExpiringMap`] whose values can weigh at most `max` in
+    /// total. When an insert would go over this limit, expired entries are
+    /// vacuumed, and if that isn't enough the entries which expire soonest are
+    /// evicted. Each value is weighed when it is inserted, so changing it in
+    /// place doesn't change its weight. An entry which weighs more than `max`
+    /// by itself is still inserted, once everything else has been evicted.
+    pub fn with_max_weight(max: usize) -> Self
+    where
+        V: Weight,
+    {
+        let mut map = Self::new();
+        map.weigher = Some(Weigher {
+            weigh: V::weight,
+            max,
+        });
+        map
+    }
+
+    /// Create a new [`ExpiringMap`] which shrinks itself after vacuums
+    /// according to `policy`
+    pub fn with_shrink_policy(policy: ShrinkPolicy) -> Self {
+        let mut map = Self::new();
+        map.shrink_policy = policy;
+        map
+    }
+
+    /// Create a new [`ExpiringMap`] which holds at most `max` live entries.
+    /// When an insert would go over this limit, expired entries are vacuumed,
+    /// and if that isn't enough the least recently used entry is evicted.
+    ///
+    /// [`Self::insert`] only returns the value it replaced at `key`. An entry
+    /// evicted to make room is passed to the
+    /// [eviction listener](Self::on_evict), or dropped if there isn't one. Use
+    /// [`Self::insert_evicting`] to get it back instead.
+    ///
+    /// Finding the least recently used entry scans the whole map, so an insert
+    /// into a full map is O(n) when nothing has expired.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero
+    pub fn with_max_len(max: usize) -> Self {
+        assert!(max > 0, "max len must be greater than 0");
+        let mut map = Self::new();
+        map.max_len = Some(max);
+        map
+    }
+}
+
+impl<K: PartialEq + Eq + Hash, V, C: Clock> ExpiringMap<K, V, RandomState, C> {
+    /// Create a new [`ExpiringMap`] which uses `clock` to tell the time
+    pub fn with_clock(clock: C) -> Self {
+        Self::with_capacity_hasher_and_clock(0, RandomState::default(), clock)
+    }
+}
+
+#[cfg(all(feature = "std", not(feature = "wasm")))]
+impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V, RandomState, CoarseClock> {
+    /// Create a new [`ExpiringMap`] which only reads the system clock every
+    /// `resolution`, using a [`CoarseClock`]. This makes inserts and reads
+    /// cheaper under heavy load, but entries may be kept up to `resolution`
+    /// after they expire. A resolution of zero is exact.
+    pub fn with_clock_resolution(resolution: Duration) -> Self {
+        Self::with_clock(CoarseClock::new(resolution))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, S> ExpiringMap<K, V, S> {
+    /// Create a new [`ExpiringMap`] which uses `hasher` to hash keys
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_capacity_and_hasher(0, hasher)
+    }
+
+    /// Create a new [`ExpiringMap`] with the specified capacity which uses
+    /// `hasher` to hash keys
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        Self::with_capacity_hasher_and_clock(capacity, hasher, SystemClock)
+    }
+}
+
+impl<K, V, S, C> ExpiringMap<K, V, S, C> {
+    /// the default minimum size to set `last_size` to
+    const MINIMUM_VACUUM_SIZE: usize = 8;
+    /// the default growth factor which triggers a vacuum
+    const VACUUM_FACTOR: f32 = 1.5;
+
+    /// Create a new [`ExpiringMap`] with the specified capacity which uses
+    /// `hasher` to hash keys and `clock` to tell the time
+    pub fn with_capacity_hasher_and_clock(capacity: usize, hasher: S, clock: C) -> Self {
         Self {
-            inner: ExpiringMapInner::with_capacity(capacity),
+            inner: ExpiringMapInner::with_capacity_and_hasher(capacity, hasher),
             last_size: Self::MINIMUM_VACUUM_SIZE,
+            vacuum_factor: Self::VACUUM_FACTOR,
+            min_vacuum_size: Self::MINIMUM_VACUUM_SIZE,
+            trigger: None,
+            shrink_policy: ShrinkPolicy::Never,
+            vacuum_on_read: false,
+            vacuum_pending: AtomicBool::new(false),
+            default_ttl: Duration::ZERO,
+            ttl_policy: None,
+            sliding: false,
+            max_len: None,
+            on_evict: None,
+            index: None,
+            accesses: AtomicU64::new(0),
+            rng: SplitMix64::from_entropy(),
+            weigher: None,
+            weight: 0,
+            stats: Stats::default(),
+            frozen_at: None,
+            clock,
         }
     }
 
-    /// Shrinks the hashmap based on entries that should no longer be contained.
-    /// This is O(n).
-    pub fn vacuum(&mut self) {
+    /// Return a reference to the map's [`BuildHasher`]
+    pub fn hasher(&self) -> &S {
+        self.inner.hasher()
+    }
+
+    /// Return a reference to the map's [`Clock`]
+    pub const fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// the time as far as expiry is concerned, which stands still while the
+    /// map is frozen
+    fn now(&self) -> Instant
+    where
+        C: Clock,
+    {
+        self.frozen_at.unwrap_or_else(|| self.clock.now())
+    }
+}
+
+impl<K: PartialEq + Eq + Hash, V, S: BuildHasher, C: Clock> ExpiringMap<K, V, S, C> {
+    /// The TTL used by [`Self::insert_default`]. This is zero unless it was
+    /// set with [`Self::with_default_ttl`] or [`Self::set_default_ttl`].
+    pub const fn default_ttl(&self) -> Duration {
+        self.default_ttl
+    }
+
+    /// Change the TTL used by [`Self::insert_default`]. Entries which are
+    /// already in the map keep their original TTL.
+    pub const fn set_default_ttl(&mut self, ttl: Duration) {
+        self.default_ttl = ttl;
+    }
+
+    /// Whether the map is [frozen](Self::set_frozen)
+    pub const fn is_frozen(&self) -> bool {
+        self.frozen_at.is_some()
+    }
+
+    /// Freeze or unfreeze the map. While it is frozen, time stands still for
+    /// it: entries which were live when it froze stay live however long it
+    /// stays frozen, entries inserted while frozen count their TTLs from when
+    /// it unfreezes, and [`Self::vacuum`], [`Self::vacuum_partial`], and
+    /// [`Self::vacuum_due`] do nothing. Entries which had already expired stay
+    /// expired.
+    ///
+    /// Unfreezing pushes every entry's deadline back by however long the map
+    /// was frozen, so each one has exactly as long left as it did when the map
+    /// froze. This is O(n). [`ExpiryValue`]'s own methods which go by the
+    /// system clock, like [`ExpiryValue::remaining`], don't know about
+    /// freezing, and are only accurate again once the map unfreezes.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        match (self.frozen_at, frozen) {
+            (None, true) => self.frozen_at = Some(self.clock.now()),
+            (Some(at), false) => {
+                self.frozen_at = None;
+                let paused = self.clock.now().saturating_duration_since(at);
+                for value in self.inner.values_mut() {
+                    value.inserted = value.inserted.checked_add(paused).unwrap_or(value.inserted);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Shrinks the hashmap based on entries that should no longer be contained,
+    /// returning how many entries were removed. This is O(n).
+    pub fn vacuum(&mut self) -> usize {
+        self.vacuum_sparing::<K>(None)
+    }
+
+    /// vacuum, but keep `spared` even if it has expired, so it can still be
+    /// replaced in place
+    fn vacuum_sparing<Q>(&mut self, spared: Option<&Q>) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        if self.is_frozen() {
+            return 0;
+        }
+        *self.vacuum_pending.get_mut() = false;
+        let before = self.inner.len();
         // keep all the items in the set where it has been
         // less than ttl since they were added
-        let now = Instant::now();
+        let now = self.now();
+        let keep = |key: &K, expiry: &ExpiryValue<V>| {
+            expiry.not_expired_at(now) || spared.is_some_and(|spared| spared == key.borrow())
+        };
+        if let Some(listener) = &mut self.on_evict {
+            for (key, expiry) in self.inner.extract_if(|key, expiry| !keep(key, expiry)) {
+                self.weight -= expiry.weight;
+                (listener.0)(&key, expiry.value);
+            }
+        } else {
+            let weight = &mut self.weight;
+            self.inner.retain(|key, expiry| {
+                let keep = keep(key, expiry);
+                if !keep {
+                    *weight -= expiry.weight;
+                }
+                keep
+            });
+        }
+        if let Some(index) = &mut self.index {
+            let inner = &self.inner;
+            index.retain(|key, id| inner.get::<K>(key).is_some_and(|v| v.index_id == id));
+        }
+        self.update_last_size();
+        self.auto_shrink();
+        before - self.inner.len()
+    }
+
+    /// Remove every entry which was [inserted](ExpiryValue::inserted) before
+    /// `cutoff`, whether or not it has expired, returning how many were
+    /// removed. This is O(n).
+    pub fn vacuum_older_than(&mut self, cutoff: Instant) -> usize {
+        let before = self.inner.len();
+        for (key, expiry) in self
+            .inner
+            .extract_if(|_, expiry| expiry.inserted() < cutoff)
+        {
+            self.weight -= expiry.weight;
+            if let Some(listener) = &mut self.on_evict {
+                (listener.0)(&key, expiry.value);
+            }
+        }
+        if let Some(index) = &mut self.index {
+            let inner = &self.inner;
+            index.retain(|key, id| inner.get(key).is_some_and(|v| v.index_id == id));
+        }
+        self.update_last_size();
+        before - self.inner.len()
+    }
+
+    /// shrink the map after a vacuum, if the [`ShrinkPolicy`] says to
+    fn auto_shrink(&mut self) {
+        match self.shrink_policy {
+            ShrinkPolicy::Aggressive => self.inner.shrink_to_fit(),
+            ShrinkPolicy::Lazy if self.inner.len() < self.inner.capacity() / 4 => {
+                self.inner.shrink_to(self.inner.len() * 2);
+            }
+            ShrinkPolicy::Below(percent)
+                if self.inner.len().saturating_mul(100)
+                    < self.inner.capacity().saturating_mul(usize::from(percent)) =>
+            {
+                self.inner.shrink_to_fit();
+            }
+            ShrinkPolicy::Lazy | ShrinkPolicy::Below(_) | ShrinkPolicy::Never => {}
+        }
+    }
+
+    /// When the map gives memory back after vacuuming
+    pub const fn shrink_policy(&self) -> ShrinkPolicy {
+        self.shrink_policy
+    }
+
+    /// Change when the map gives memory back after vacuuming
+    pub const fn set_shrink_policy(&mut self, policy: ShrinkPolicy) {
+        self.shrink_policy = policy;
+    }
+
+    /// Whether reads may [vacuum](Self::set_vacuum_on_read) the map
+    pub const fn vacuum_on_read(&self) -> bool {
+        self.vacuum_on_read
+    }
+
+    /// Set whether reads help keep the map clean, like inserts do. This is off
+    /// by default.
+    ///
+    /// The reads which take the map mutably, [`Self::get_mut`],
+    /// [`Self::get_mut_meta`], [`Self::get_purge`], and [`Self::get_refresh`],
+    /// call [`Self::vacuum_if_needed`] first. Reads through a shared reference,
+    /// like [`Self::get`] and [`Self::contains_key`], can't remove anything, so
+    /// when one finds an expired entry it marks the map, and the next
+    /// [`Self::vacuum_if_needed`] vacuums regardless of its trigger.
+    /// [`SyncExpiringMap::get`] takes the write lock to do this right away.
+    ///
+    /// Turning it on keeps a map which is rarely written to from holding onto
+    /// expired entries, at the cost of some reads and inserts taking O(n) time
+    /// instead of O(1).
+    pub const fn set_vacuum_on_read(&mut self, enabled: bool) {
+        self.vacuum_on_read = enabled;
+    }
+
+    /// If a shared read has found an expired entry since the last vacuum, with
+    /// [`Self::vacuum_on_read`] set
+    #[cfg(feature = "std")]
+    pub(crate) fn vacuum_pending(&self) -> bool {
+        self.vacuum_pending.load(Ordering::Relaxed)
+    }
+
+    /// mark the map for vacuuming if [`Self::vacuum_on_read`] is set and a
+    /// shared read found `value` expired as of `now`
+    fn note_read(&self, value: Option<&ExpiryValue<V>>, now: Instant) {
+        if self.vacuum_on_read && value.is_some_and(|v| v.expired_at(now)) {
+            self.vacuum_pending.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// vacuum before a mutable read, if [`Self::vacuum_on_read`] is set
+    fn vacuum_before_mut_read(&mut self) {
+        if self.vacuum_on_read {
+            self.vacuum_if_needed();
+        }
+    }
+
+    /// Keep only the entries which have not expired and for which `f` returns
+    /// true. `f` is only called on live entries. This is O(n).
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        self.retain_meta(|key, expiry| f(key, &expiry.value));
+    }
+
+    /// Like [`Self::retain`], but `f` is given each entry's expiry data, so it
+    /// can decide based on how long the entry has [left](ExpiryValue::remaining)
+    /// or when it was [inserted](ExpiryValue::inserted). This is O(n).
+    pub fn retain_meta<F: FnMut(&K, &ExpiryValue<V>) -> bool>(&mut self, mut f: F) {
+        let now = self.now();
+        let mut keep =
+            |key: &K, expiry: &mut ExpiryValue<V>| expiry.not_expired_at(now) && f(key, expiry);
+        if let Some(listener) = &mut self.on_evict {
+            for (key, expiry) in self.inner.extract_if(|key, expiry| !keep(key, expiry)) {
+                self.weight -= expiry.weight;
+                (listener.0)(&key, expiry.value);
+            }
+        } else {
+            let weight = &mut self.weight;
+            self.inner.retain(|key, expiry| {
+                let kept = keep(key, expiry);
+                if !kept {
+                    *weight -= expiry.weight;
+                }
+                kept
+            });
+        }
+        if let Some(index) = &mut self.index {
+            let inner = &self.inner;
+            index.retain(|key, id| inner.get(key).is_some_and(|v| v.index_id == id));
+        }
+        self.update_last_size();
+    }
+
+    /// Check at most `max_entries` deadlines from the
+    /// [expiry index](Self::enable_expiry_index), soonest first, and return how
+    /// many expired entries were removed. This spreads the work of
+    /// [`Self::vacuum_due`] over many calls, each of which does a bounded
+    /// amount of work. Deadlines which are stale because their entry was
+    /// removed or overwritten count against `max_entries` too. If no index is
+    /// enabled, the first call enables one, which is O(n).
+    pub fn vacuum_partial(&mut self, max_entries: usize) -> usize
+    where
+        K: Clone,
+    {
+        if self.is_frozen() {
+            return 0;
+        }
+        if self.index.is_none() {
+            self.enable_expiry_index();
+        }
+        let now = self.now();
+        let mut budget = max_entries;
+        let mut removed = 0;
+        while let Some((key, value)) = self.pop_due(now, &mut budget) {
+            self.weight -= value.weight;
+            if let Some(listener) = &mut self.on_evict {
+                (listener.0)(&key, value.value);
+            }
+            removed += 1;
+        }
+        self.update_last_size();
+        removed
+    }
+
+    /// Start keeping an index of expiry deadlines, so [`Self::vacuum_due`] can
+    /// remove expired entries without scanning the whole map. This clones
+    /// every key into the index, which costs memory and a clone per insert.
+    pub fn enable_expiry_index(&mut self)
+    where
+        K: Clone,
+    {
+        let mut index = ExpiryIndex::new(K::clone);
+        for (key, value) in &mut self.inner {
+            index.push(key, value);
+        }
+        self.index = Some(index);
+    }
+
+    /// Like [`Self::enable_expiry_index`], but the index is a hierarchical
+    /// timing wheel with ticks of `resolution`, rather than a heap. Inserting
+    /// into a wheel is O(1), and [`Self::tick`] removes expired entries in O(1)
+    /// amortized time each, at the cost of entries only being found once the
+    /// tick after their deadline has passed. This replaces any index which
+    /// was already enabled.
+    pub fn enable_timer_wheel(&mut self, resolution: Duration)
+    where
+        K: Clone,
+    {
+        let mut index = ExpiryIndex::with_wheel(K::clone, self.now(), resolution);
+        for (key, value) in &mut self.inner {
+            index.push(key, value);
+        }
+        self.index = Some(index);
+    }
+
+    /// Advance the [timer wheel](Self::enable_timer_wheel) to now, removing
+    /// every entry whose deadline it passes and returning how many were
+    /// removed. This is the same as [`Self::vacuum_due`].
+    pub fn tick(&mut self) -> usize {
+        self.vacuum_due()
+    }
+
+    /// Remove expired entries using the expiry index, returning how many were
+    /// removed. This is O(k log n) for k due entries with a heap index, or
+    /// O(k) with a [timer wheel](Self::enable_timer_wheel), instead of the
+    /// O(n) of [`Self::vacuum`], which it falls back to if no index is
+    /// [enabled](Self::enable_expiry_index). Entries whose TTL was shortened
+    /// after they were inserted may not be removed until their original
+    /// deadline.
+    pub fn vacuum_due(&mut self) -> usize {
+        if self.is_frozen() {
+            return 0;
+        }
+        let now = self.now();
+        if self.index.is_none() {
+            return self.vacuum();
+        }
+        let mut removed = 0;
+        let mut unbounded = usize::MAX;
+        while let Some((key, value)) = self.pop_due(now, &mut unbounded) {
+            self.weight -= value.weight;
+            if let Some(listener) = &mut self.on_evict {
+                (listener.0)(&key, value.value);
+            }
+            removed += 1;
+        }
+        self.update_last_size();
+        removed
+    }
+
+    /// remove the first entry the expiry index finds expired as of `now`,
+    /// dropping the stale records it passes on the way, and looking at no more
+    /// than `budget` records
+    fn pop_due(&mut self, now: Instant, budget: &mut usize) -> Option<(K, ExpiryValue<V>)> {
+        let index = self.index.as_mut()?;
+        while *budget > 0 {
+            *budget -= 1;
+            let record = index.pop_due(now)?;
+            match self.inner.get(&record.key) {
+                Some(value) if value.index_id == record.id && value.not_expired_at(now) => {
+                    // the deadline was pushed back since this record was made
+                    if let Some(at) = value.deadline() {
+                        index.repush(Deadline { at, ..record });
+                    }
+                }
+                Some(value) if value.index_id == record.id => {
+                    return self.inner.remove_entry(&record.key);
+                }
+                // this record is stale, its entry was removed or overwritten
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Register a callback which is run with each entry as it leaves the map,
+    /// whether it was vacuumed, removed, retained out, cleared, evicted to make
+    /// room, or replaced after expiring. It runs synchronously inside the call
+    /// which removed the entry, right before the value is dropped, so it isn't run
+    /// for entries which are handed back to the caller, like those from
+    /// [`Self::remove_entry`] or [`Self::drain_expired`]. This replaces any
+    /// callback which was already registered.
+    pub fn on_evict<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, V) + Send + Sync + 'static,
+    {
+        self.on_evict = Some(EvictionListener(Box::new(f)));
+    }
+
+    /// Send every entry which leaves the map through `tx`, so another thread
+    /// can process them. This is an [eviction listener](Self::on_evict), so it
+    /// sees expired entries as they are vacuumed or replaced, as well as
+    /// entries which are removed, retained out, cleared, or evicted to make
+    /// room, and it replaces any listener which was already registered. Send
+    /// errors are ignored, so entries are dropped once the receiver is gone.
+    #[cfg(feature = "std")]
+    pub fn set_expiry_sink(&mut self, tx: std::sync::mpsc::Sender<(K, V)>)
+    where
+        K: Clone + Send + 'static,
+        V: Send + 'static,
+    {
+        self.on_evict(move |key: &K, value| {
+            let _ = tx.send((key.clone(), value));
+        });
+    }
+
+    /// run the eviction callback, if there is one
+    fn evicted(&mut self, key: &K, value: V) {
+        if let Some(listener) = &mut self.on_evict {
+            (listener.0)(key, value);
+        }
+    }
+
+    /// execute a vacuum if the map has grown by more than the
+    /// [vacuum factor](Self::vacuum_factor), its
+    /// [trigger](Self::set_vacuum_trigger) says to, or a
+    /// [read](Self::set_vacuum_on_read) has found an expired entry, returning
+    /// how many entries were removed if it did
+    pub fn vacuum_if_needed(&mut self) -> Option<usize> {
+        if *self.vacuum_pending.get_mut() {
+            return Some(self.vacuum());
+        }
+        let len = self.inner.len();
+        let due = match &self.trigger {
+            Some(trigger) => trigger.0.should_vacuum(self.last_size, len),
+            None => GrowthTrigger::new(self.vacuum_factor).should_vacuum(self.last_size, len),
+        };
+        due.then(|| self.vacuum())
+    }
+
+    /// Decide when [`Self::vacuum_if_needed`] vacuums with `trigger`, instead
+    /// of the [vacuum factor](Self::vacuum_factor). Clones of the map share
+    /// the trigger.
+    pub fn set_vacuum_trigger<T>(&mut self, trigger: T)
+    where
+        T: VacuumTrigger + Send + Sync + 'static,
+    {
+        self.trigger = Some(Trigger(Arc::new(trigger)));
+    }
+
+    /// Remove one expired entry from the map, whichever is found first, and
+    /// return its key and value, or [`None`] if nothing has expired. This
+    /// stops at the first expired entry, so calling it once per tick spreads
+    /// cleanup out, but a map with few expired entries may still be scanned
+    /// in full. With an [expiry index](Self::enable_expiry_index), entries
+    /// which are due are found through it first.
+    pub fn pop_expired_one(&mut self) -> Option<(K, V)> {
+        let now = self.now();
+        let mut unbounded = usize::MAX;
+        let (key, value) = self.pop_due(now, &mut unbounded).or_else(|| {
+            self.inner
+                .extract_if(|_, expiry| expiry.expired_at(now))
+                .next()
+        })?;
+        self.weight -= value.weight;
+        self.update_last_size();
+        Some((key, value.value))
+    }
+
+    /// Remove every expired entry from the map, returning their keys and values
+    /// in no particular order. This is O(n).
+    pub fn drain_expired(&mut self) -> Vec<(K, V)> {
+        let now = self.now();
+        let weight = &mut self.weight;
+        let expired = self
+            .inner
+            .extract_if(|_, expiry| !expiry.not_expired_at(now))
+            .map(|(k, v)| {
+                *weight -= v.weight;
+                (k, v.value)
+            })
+            .collect();
+        self.update_last_size();
+        expired
+    }
+
+    /// Split the map into its live entries, which stay in the map with their
+    /// deadlines and settings unchanged, and its expired ones, returned as a
+    /// [`Vec`] in no particular order. This is O(n).
+    pub fn split_expired(mut self) -> (Self, Vec<(K, V)>) {
+        let expired = self.drain_expired();
+        (self, expired)
+    }
+
+    /// record the current size of the map as the size after a vacuum
+    fn update_last_size(&mut self) {
+        self.last_size = self.inner.len().max(self.min_vacuum_size);
+    }
+
+    /// How many times bigger than [`Self::last_size`] the map can grow before
+    /// [`Self::vacuum_if_needed`] vacuums it. This defaults to 1.5.
+    pub const fn vacuum_factor(&self) -> f32 {
+        self.vacuum_factor
+    }
+
+    /// Change the [vacuum factor](Self::vacuum_factor). Lower values vacuum
+    /// more often, and higher ones less.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not greater than 1.0
+    pub fn set_vacuum_factor(&mut self, factor: f32) {
+        assert!(factor > 1.0, "vacuum factor must be greater than 1.0");
+        self.vacuum_factor = factor;
+    }
+
+    /// The smallest [`Self::last_size`] is ever set to, so small maps aren't
+    /// vacuumed on every insert. This defaults to 8.
+    pub const fn minimum_vacuum_size(&self) -> usize {
+        self.min_vacuum_size
+    }
+
+    /// Change the [minimum vacuum size](Self::minimum_vacuum_size)
+    pub fn set_minimum_vacuum_size(&mut self, size: usize) {
+        self.min_vacuum_size = size;
+        self.last_size = self.last_size.max(size);
+    }
+
+    /// If the value exists and has not expired, return its expiry data
+    pub fn get_meta<Q>(&self, key: &Q) -> Option<&ExpiryValue<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.get_meta_at(key, self.now())
+    }
+
+    /// [`Self::get_meta`] as of `now`
+    fn get_meta_at<Q>(&self, key: &Q, now: Instant) -> Option<&ExpiryValue<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let value = self.inner.get(key);
+        self.note_read(value, now);
+        if !self.stats.read(value, now) {
+            return None;
+        }
+        let value = value?;
+        self.touch(value, now);
+        Some(value)
+    }
+
+    /// If the value exists and has not expired, return it
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        // get meta checks expiry for us
+        self.get_meta(key).map(|v| &v.value)
+    }
+
+    /// Look up each of `keys`, returning their live values in the same order,
+    /// with [`None`] for the ones which are missing or expired. The clock is
+    /// only read once, so every key is checked as of the same moment.
+    pub fn get_all<'a, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        let now = self.now();
+        keys.into_iter()
+            .map(|key| self.get_meta_at(key, now).map(|v| &v.value))
+            .collect()
+    }
+
+    /// Return the expiry data stored for this key, even if it has expired.
+    /// This does not count as a read for sliding expiration or LRU eviction.
+    pub fn peek_meta<Q>(&self, key: &Q) -> Option<&ExpiryValue<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.inner.get(key)
+    }
+
+    /// Return the value stored for this key, even if it has expired.
+    /// This does not count as a read for sliding expiration or LRU eviction.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.peek_meta(key).map(|v| &v.value)
+    }
+
+    /// If a key exists for this value, get both the key and value if it is not expired
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.get_key_value_meta(key).map(|(k, v)| (k, &v.value))
+    }
+
+    /// If a key exists for this value, get both the stored key and its expiry
+    /// data if it is not expired
+    pub fn get_key_value_meta<Q>(&self, key: &Q) -> Option<(&K, &ExpiryValue<V>)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let now = self.now();
+        let found = self.inner.get_key_value(key);
+        self.note_read(found.map(|(_, v)| v), now);
+        if !self.stats.read(found.map(|(_, v)| v), now) {
+            return None;
+        }
+        let (k, v) = found?;
+        self.touch(v, now);
+        Some((k, v))
+    }
+
+    /// Return the stored key and expiry data for this key, even if it has
+    /// expired. This does not count as a read for sliding expiration or LRU
+    /// eviction.
+    pub fn peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &ExpiryValue<V>)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.inner.get_key_value(key)
+    }
+
+    /// Get a mutable reference to the value and expiry data pointed to by a
+    /// key, if it is not expired
+    pub fn get_mut_meta<Q>(&mut self, key: &Q) -> Option<&mut ExpiryValue<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.vacuum_before_mut_read();
+        let now = self.now();
+        let value = self.inner.get_mut(key);
+        if !self.stats.read(value.as_deref(), now) {
+            return None;
+        }
+        let value = value?;
+        #[cfg(feature = "access-count")]
+        value.hit();
+        value.touch(now, self.sliding, self.max_len.map(|_| &self.accesses));
+        Some(value)
+    }
+
+    /// If the value exists and has not expired, return it. If it has expired,
+    /// remove it from the map, rather than waiting for the next vacuum.
+    pub fn get_purge<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.vacuum_before_mut_read();
+        let now = self.now();
+        if !self.stats.read(self.inner.get(key), now) {
+            if let Some((key, value)) = self.inner.remove_entry(key) {
+                self.weight -= value.weight;
+                self.evicted(&key, value.value);
+            }
+            return None;
+        }
+        let value = self.inner.get(key)?;
+        self.touch(value, now);
+        Some(&value.value)
+    }
+
+    /// Get a mutable reference to the value pointed to by a key, if it is not expired
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.vacuum_before_mut_read();
+        let now = self.now();
+        let value = self.inner.get_mut(key);
+        if !self.stats.read(value.as_deref(), now) {
+            return None;
+        }
+        let value = value?;
+        #[cfg(feature = "access-count")]
+        value.hit();
+        value.touch(now, self.sliding, self.max_len.map(|_| &self.accesses));
+        Some(&mut value.value)
+    }
+
+    /// Get mutable references to the values of several keys at once. Returns
+    /// [`None`] if any key is missing, expired, or given more than once.
+    pub fn get_many_mut<Q, const N: usize>(&mut self, keys: [&Q; N]) -> Option<[&mut V; N]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].contains(key) {
+                return None;
+            }
+        }
+        let now = self.now();
+        let accesses = self.max_len.map(|_| &self.accesses);
+        let stats = &self.stats;
+        // every key is read and counted, even after one misses
+        let values: Vec<Option<&mut V>> = self
+            .inner
+            .get_disjoint_mut(keys)
+            .into_iter()
+            .map(|value| {
+                if !stats.read(value.as_deref(), now) {
+                    return None;
+                }
+                let value = value?;
+                #[cfg(feature = "access-count")]
+                value.hit();
+                value.touch(now, self.sliding, accesses);
+                Some(&mut value.value)
+            })
+            .collect();
+        let values: Vec<&mut V> = values.into_iter().collect::<Option<_>>()?;
+        values.try_into().ok()
+    }
+
+    /// If the value exists and has not expired, restart its TTL from now and
+    /// return it. Expired entries are never refreshed.
+    pub fn get_refresh<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.vacuum_before_mut_read();
+        let now = self.now();
+        let value = self.inner.get_mut(key);
+        if !self.stats.read(value.as_deref(), now) {
+            return None;
+        }
+        let value = value?;
+        value.reset(now);
+        #[cfg(feature = "access-count")]
+        value.hit();
+        value.touch(now, false, self.max_len.map(|_| &self.accesses));
+        Some(&value.value)
+    }
+
+    /// record a read of `value` for sliding expiration, LRU eviction and
+    /// [`ExpiryValue::access_count`]
+    fn touch(&self, value: &ExpiryValue<V>, now: Instant) {
+        #[cfg(feature = "access-count")]
+        value.hit();
+        value.touch(now, self.sliding, self.max_len.map(|_| &self.accesses));
+    }
+
+    /// The most live entries this map will hold, if it is bounded
+    pub const fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    /// Insert a value into the map, returning the entry which was evicted to
+    /// make room for it, if any. If `key` already held a live value, nothing is
+    /// evicted and the old value is dropped; use [`Self::insert`] to get it.
+    /// Finding the least recently used entry is O(n).
+    pub fn insert_evicting(&mut self, key: K, value: V, ttl: Duration) -> Option<(K, V)> {
+        let evicted = self.make_room(&key);
+        self.insert_entry(key, value, Some(ttl));
+        evicted
+    }
+
+    /// if the map is bounded and inserting `key` would go over the limit,
+    /// vacuum, then evict the least recently used entry if that wasn't enough
+    fn make_room(&mut self, key: &K) -> Option<(K, V)> {
+        let max = self.max_len?;
+        if self.inner.len() < max || self.inner.contains_key(key) {
+            return None;
+        }
+        self.vacuum();
+        if self.inner.len() < max {
+            return None;
+        }
+        let oldest = self
+            .inner
+            .values()
+            .map(|v| v.accessed.load(Ordering::Relaxed))
+            .min()?;
+        let (key, value) = self
+            .inner
+            .extract_if(|_, v| v.accessed.load(Ordering::Relaxed) == oldest)
+            .next()?;
+        self.weight -= value.weight;
+        self.stats.evicted();
+        Some((key, value.value))
+    }
+
+    /// if the map has a weight limit and adding `incoming` to it at `key` would
+    /// go over, vacuum, then evict the entries which expire soonest until it
+    /// fits
+    fn make_weight_room<Q>(&mut self, key: &Q, incoming: usize)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let Some(weigher) = self.weigher else {
+            return;
+        };
+        // whatever is at `key` now will be replaced, so it doesn't count
+        let fits = |map: &Self| {
+            let replaced = map.inner.get(key).map_or(0, |v| v.weight);
+            map.weight - replaced + incoming <= weigher.max
+        };
+        if fits(self) {
+            return;
+        }
+        self.vacuum_sparing(Some(key));
+        let now = self.now();
+        while !fits(self) {
+            let Some(soonest) = self
+                .inner
+                .iter()
+                .filter(|(k, _)| (*k).borrow() != key)
+                .map(|(_, v)| v.remaining_at(now))
+                .min()
+            else {
+                return;
+            };
+            let evicted = self
+                .inner
+                .extract_if(|k, v| k.borrow() != key && v.remaining_at(now) == soonest)
+                .next();
+            if let Some((key, value)) = evicted {
+                self.weight -= value.weight;
+                self.stats.evicted();
+                self.evicted(&key, value.value);
+            }
+        }
+    }
+
+    /// Start counting how many reads hit and miss, and how many entries are
+    /// evicted to make room, for [`Self::stats`]. Counting is off by default,
+    /// so maps which don't need it don't pay for it. Enabling it again does
+    /// nothing.
+    pub fn enable_stats(&mut self) {
+        self.stats.enable();
+    }
+
+    /// How many reads have hit and missed, and how many entries have been
+    /// evicted to make room, since stats were [enabled](Self::enable_stats)
+    /// or [reset](Self::reset_stats), or [`None`] if they haven't been
+    /// enabled. Reads are counted by the `get` family of methods and
+    /// [`Self::contains_key`], but not by [`Self::peek`] or iteration.
+    pub fn stats(&self) -> Option<CacheStats> {
+        self.stats.snapshot()
+    }
+
+    /// Set every counter in [`Self::stats`] back to zero
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// The total weight of every value in the map, including expired ones
+    /// which haven't been vacuumed yet. This is always zero unless the map was
+    /// created with [`Self::with_max_weight`].
+    pub const fn current_weight(&self) -> usize {
+        self.weight
+    }
+
+    /// The most the values in this map can weigh in total, if it has a limit
+    pub fn max_weight(&self) -> Option<usize> {
+        self.weigher.map(|w| w.max)
+    }
+
+    /// How long the entry at `key` has left, if it exists and has not expired.
+    /// This is [`Duration::MAX`] for persistent entries, and does not count as
+    /// a read.
+    pub fn remaining<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let now = self.now();
+        let value = self.inner.get(key).filter(|v| v.not_expired_at(now))?;
+        Some(value.remaining_at(now))
+    }
+
+    /// The TTL of the entry at `key`, if it exists and has not expired. This is
+    /// [`Duration::MAX`] for persistent entries, and does not count as a read.
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let now = self.now();
+        let value = self.inner.get(key).filter(|v| v.not_expired_at(now))?;
+        Some(value.ttl())
+    }
+
+    /// When the entry at `key` will expire, if it exists and has not expired.
+    /// Persistent entries and those too far in the future to be represented
+    /// return [`None`].
+    pub fn expires_at<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let now = self.now();
         self.inner
-            .retain(|_, expiry| now.duration_since(expiry.inserted) < expiry.ttl);
-        if self.inner.len() > Self::MINIMUM_VACUUM_SIZE {
-            self.last_size = self.inner.len();
+            .get(key)
+            .filter(|v| v.not_expired_at(now))?
+            .deadline()
+    }
+
+    /// Add `extra` to the TTL of the entry at `key`, returning false if it
+    /// doesn't exist or has already expired
+    pub fn extend_ttl<Q>(&mut self, key: &Q, extra: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.live_mut(key)
+            .map(|v| v.ttl = v.ttl.map(|ttl| ttl.saturating_add(extra)))
+            .is_some()
+    }
+
+    /// Replace the TTL of the entry at `key`, returning false if it doesn't
+    /// exist or has already expired. The TTL still counts from when the entry
+    /// was inserted.
+    pub fn set_ttl<Q>(&mut self, key: &Q, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.live_mut(key).map(|v| v.set_ttl(ttl)).is_some()
+    }
+
+    /// Expire the entry at `key` now without removing it, so reads skip it but
+    /// [`Self::peek`] still sees it until it is vacuumed. Returns false if it
+    /// doesn't exist or has already expired.
+    pub fn expire_now<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.live_mut(key)
+            .map(|v| v.set_ttl(Duration::ZERO))
+            .is_some()
+    }
+
+    /// Restart the TTL of the entry at `key` from now, returning false if it
+    /// doesn't exist or has already expired
+    pub fn renew<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let now = self.now();
+        self.live_mut(key).map(|v| v.reset(now)).is_some()
+    }
+
+    /// Run `f` on the value at `key` and restart it from now with a TTL of
+    /// `ttl`, returning false without calling `f` if it doesn't exist or has
+    /// already expired. This is [`Self::get_mut`], [`Self::renew`], and
+    /// [`Self::set_ttl`] with a single lookup.
+    pub fn update<Q, F>(&mut self, key: &Q, ttl: Duration, f: F) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&mut V),
+    {
+        let now = self.now();
+        let Some(value) = self.live_mut(key) else {
+            return false;
+        };
+        f(&mut value.value);
+        value.reset(now);
+        value.set_ttl(ttl);
+        true
+    }
+
+    /// Restart every live entry from now with a TTL of `ttl`, keeping its value.
+    /// Expired entries are left for the next vacuum. This is O(n).
+    pub fn reset_all_ttls(&mut self, ttl: Duration) {
+        let now = self.now();
+        for (key, value) in &mut self.inner {
+            if value.not_expired_at(now) {
+                value.reset(now);
+                value.set_ttl(ttl);
+                if let Some(index) = &mut self.index {
+                    index.push(key, value);
+                }
+            }
+        }
+    }
+
+    /// Replace the value of the entry at `key`, keeping when it was inserted
+    /// and its TTL, and return the old value. Returns [`None`] without
+    /// inserting if `key` doesn't exist or has expired.
+    pub fn replace_value<Q>(&mut self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let now = self.now();
+        if !self.inner.get(key).is_some_and(|v| v.not_expired_at(now)) {
+            return None;
+        }
+        let weight = self.weigher.map_or(0, |w| (w.weigh)(&value));
+        self.make_weight_room(key, weight);
+        let entry = self.inner.get_mut(key)?;
+        self.weight = self.weight - entry.weight + weight;
+        entry.weight = weight;
+        Some(core::mem::replace(&mut entry.value, value))
+    }
+
+    /// the entry at `key` if it has not expired, without counting as a read
+    fn live_mut<Q>(&mut self, key: &Q) -> Option<&mut ExpiryValue<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let now = self.now();
+        self.inner.get_mut(key).filter(|v| v.not_expired_at(now))
+    }
+
+    /// Whether reads restart the TTL of the entry they read
+    pub const fn sliding(&self) -> bool {
+        self.sliding
+    }
+
+    /// Set whether reads through [`Self::get`], [`Self::get_meta`],
+    /// [`Self::get_key_value`], [`Self::get_mut`], and [`Self::contains_key`]
+    /// restart the TTL of the entry they read, turning the map into an idle
+    /// timeout cache. Expired entries are never refreshed.
+    pub const fn set_sliding(&mut self, sliding: bool) {
+        self.sliding = sliding;
+    }
+
+    /// Insert a value into the map, returning the old value if it has not expired and existed.
+    /// If the map is [bounded](Self::with_max_len), this may evict another
+    /// entry, which is passed to the [eviction listener](Self::on_evict) rather
+    /// than returned; see [`Self::insert_evicting`].
+    pub fn insert(&mut self, key: K, value: V, ttl: Duration) -> Option<ExpiryValue<V>> {
+        self.insert_entry(key, value, Some(ttl))
+    }
+
+    /// Insert a value into the map which never expires, returning the old
+    /// value if it has not expired and existed. Persistent entries are never
+    /// removed by [`Self::vacuum`].
+    pub fn insert_persistent(&mut self, key: K, value: V) -> Option<ExpiryValue<V>> {
+        self.insert_entry(key, value, None)
+    }
+
+    /// Insert a value into the map which expires at `deadline`, returning the
+    /// old value if it has not expired and existed. If `deadline` has already
+    /// passed, the entry is expired as soon as it is inserted.
+    pub fn insert_until(&mut self, key: K, value: V, deadline: Instant) -> Option<ExpiryValue<V>> {
+        self.vacuum_if_needed();
+        let now = self.now();
+        let ttl = deadline.saturating_duration_since(now);
+        self.insert_at(key, value, Some(ttl), now)
+    }
+
+    fn insert_entry(&mut self, key: K, value: V, ttl: Option<Duration>) -> Option<ExpiryValue<V>> {
+        self.vacuum_if_needed();
+        self.insert_at(key, value, ttl, self.now())
+    }
+
+    /// make room for a new entry at `key`, then create it and start tracking
+    /// it, ready to be stored
+    fn prepare_entry(
+        &mut self,
+        key: &K,
+        value: V,
+        ttl: Option<Duration>,
+        now: Instant,
+    ) -> ExpiryValue<V> {
+        if let Some((key, value)) = self.make_room(key) {
+            self.evicted(&key, value);
+        }
+        let weight = self.weigher.map_or(0, |w| (w.weigh)(&value));
+        self.make_weight_room(key, weight);
+        let mut entry = ExpiryValue::new(value, ttl, now);
+        entry.weight = weight;
+        self.weight += weight;
+        entry.touch(now, self.sliding, self.max_len.map(|_| &self.accesses));
+        if let Some(index) = &mut self.index {
+            index.push(key, &mut entry);
+        }
+        entry
+    }
+
+    /// insert an entry as of `now`, without checking if a vacuum is needed
+    fn insert_at(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        now: Instant,
+    ) -> Option<ExpiryValue<V>> {
+        let entry = self.prepare_entry(&key, value, ttl, now);
+        match self.inner.entry(key) {
+            hash_map::Entry::Occupied(mut occupied) => {
+                let old = occupied.insert(entry);
+                self.weight -= old.weight;
+                if old.not_expired_at(now) {
+                    return Some(old);
+                }
+                if let Some(listener) = &mut self.on_evict {
+                    (listener.0)(occupied.key(), old.value);
+                }
+                None
+            }
+            hash_map::Entry::Vacant(vacant) => {
+                vacant.insert(entry);
+                None
+            }
+        }
+    }
+
+    /// If `key` has a live value, replace it with `value` and restart it with
+    /// `ttl` in place, returning the old value. Otherwise insert it like
+    /// [`Self::insert`]. When the entry is live, the key already in the map is
+    /// kept and the new one is only used to find it, so a heap-allocated key
+    /// such as a [string](alloc::string::String) or [`Vec`] is dropped
+    /// rather than stored.
+    pub fn refresh_or_insert(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let now = self.now();
+        if !self.inner.get(&key).is_some_and(|v| v.not_expired_at(now)) {
+            self.insert_entry(key, value, Some(ttl));
+            return None;
+        }
+        let weight = self.weigher.map_or(0, |w| (w.weigh)(&value));
+        self.make_weight_room(&key, weight);
+        let entry = self.inner.get_mut(&key)?;
+        entry.reset(now);
+        entry.set_ttl(ttl);
+        entry.touch(now, false, self.max_len.map(|_| &self.accesses));
+        self.weight = self.weight - entry.weight + weight;
+        entry.weight = weight;
+        if let Some(index) = &mut self.index {
+            index.push(&key, entry);
+        }
+        Some(core::mem::replace(&mut entry.value, value))
+    }
+
+    /// Insert every key and value from `items` with the same `ttl`, reading the
+    /// clock and checking whether a vacuum is needed only once for the whole
+    /// batch. Old values are dropped.
+    pub fn insert_batch<I: IntoIterator<Item = (K, V)>>(&mut self, items: I, ttl: Duration) {
+        let items = items.into_iter();
+        self.vacuum_if_needed();
+        self.inner.reserve(items.size_hint().0);
+        let now = self.now();
+        for (key, value) in items {
+            self.insert_at(key, value, Some(ttl), now);
+        }
+    }
+
+    /// Insert a value into the map only if `key` has no live value. Expired
+    /// values are replaced. If there is a live value it is left alone, without
+    /// counting as a read, and `value` is handed back.
+    ///
+    /// # Errors
+    ///
+    /// Returns `value` if `key` already has a live value
+    pub fn insert_if_absent(&mut self, key: K, value: V, ttl: Duration) -> Result<(), V> {
+        let now = self.now();
+        if self.inner.get(&key).is_some_and(|v| v.not_expired_at(now)) {
+            return Err(value);
+        }
+        self.insert_entry(key, value, Some(ttl));
+        Ok(())
+    }
+
+    /// Insert a value into the map only if `key` has no live value, returning
+    /// a mutable reference to it. Expired values are replaced.
+    ///
+    /// # Errors
+    /// If `key` already has a live value, returns an [`OccupiedError`] with a
+    /// mutable reference to it and the value which wasn't inserted
+    pub fn try_insert(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<&mut V, OccupiedError<'_, V>> {
+        self.vacuum_if_needed();
+        let now = self.now();
+        let incoming = if self.inner.get(&key).is_some_and(|v| v.not_expired_at(now)) {
+            Err(value)
         } else {
-            self.last_size = Self::MINIMUM_VACUUM_SIZE;
+            Ok(self.prepare_entry(&key, value, Some(ttl), now))
+        };
+        match (self.inner.entry(key), incoming) {
+            (hash_map::Entry::Occupied(occupied), Err(value)) => Err(OccupiedError {
+                existing: &mut occupied.into_mut().value,
+                value,
+            }),
+            (hash_map::Entry::Occupied(mut expired), Ok(entry)) => {
+                let old = expired.insert(entry);
+                self.weight -= old.weight;
+                if let Some(listener) = &mut self.on_evict {
+                    (listener.0)(expired.key(), old.value);
+                }
+                Ok(&mut expired.into_mut().value)
+            }
+            (hash_map::Entry::Vacant(vacant), Ok(entry)) => Ok(&mut vacant.insert(entry).value),
+            (hash_map::Entry::Vacant(_), Err(_)) => unreachable!("the key was checked to be live"),
         }
     }
 
-    /// execute a vacuum if the map has grown by more than 1.5 times
-    pub fn vacuum_if_needed(&mut self) {
-        if (self.last_size * 3) / 2 < self.inner.len() {
-            self.vacuum();
+    /// Insert a value into the map with a TTL picked uniformly at random from
+    /// `ttl - jitter` to `ttl + jitter`, so entries inserted together don't all
+    /// expire at once. The TTL is never less than zero. Returns the old value
+    /// if it has not expired and existed.
+    pub fn insert_jittered(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Duration,
+        jitter: Duration,
+    ) -> Option<ExpiryValue<V>> {
+        let low = ttl.saturating_sub(jitter);
+        let span = ttl.saturating_add(jitter).saturating_sub(low);
+        let ttl = self.rng.duration_from(low, span);
+        self.insert(key, value, ttl)
+    }
+
+    /// Seed the random number generator used by [`Self::insert_jittered`], to
+    /// make its TTLs reproducible. It is seeded randomly by default.
+    pub const fn set_jitter_seed(&mut self, seed: u64) {
+        self.rng = SplitMix64::new(seed);
+    }
+
+    /// Move every live entry from `other` into this map, keeping their
+    /// original expiry times. When a key is live in both, whichever entry has
+    /// longer remaining is kept. Expired entries in `other` are dropped.
+    pub fn merge<T>(&mut self, other: ExpiringMap<K, V, T, C>) {
+        self.vacuum_if_needed();
+        let now = self.now();
+        for (key, mut value) in other.inner {
+            if !value.not_expired_at(now) {
+                continue;
+            }
+            let keep_ours = self.inner.get(&key).is_some_and(|ours| {
+                ours.not_expired_at(now) && ours.remaining_at(now) >= value.remaining_at(now)
+            });
+            if keep_ours {
+                continue;
+            }
+            if let Some((key, value)) = self.make_room(&key) {
+                self.evicted(&key, value);
+            }
+            value.weight = self.weigher.map_or(0, |w| (w.weigh)(&value.value));
+            self.make_weight_room(&key, value.weight);
+            self.weight += value.weight;
+            value.touch(now, false, self.max_len.map(|_| &self.accesses));
+            if let Some(index) = &mut self.index {
+                index.push(&key, &mut value);
+            } else {
+                value.index_id = 0;
+            }
+            match self.inner.entry(key) {
+                hash_map::Entry::Occupied(mut occupied) => {
+                    let old = occupied.insert(value);
+                    self.weight -= old.weight;
+                    if let Some(listener) = &mut self.on_evict {
+                        (listener.0)(occupied.key(), old.value);
+                    }
+                }
+                hash_map::Entry::Vacant(vacant) => {
+                    vacant.insert(value);
+                }
+            }
         }
     }
 
-    /// If the value exists and has not expired, return its expiry data
-    pub fn get_meta<Q>(&self, key: &Q) -> Option<&ExpiryValue<V>>
+    /// Create a map from the entries of `map`, which all live for `ttl`
+    pub fn from_hashmap_with_ttl(map: HashMap<K, V, S>, ttl: Duration) -> Self
     where
-        K: Borrow<Q>,
-        Q: ?Sized + Hash + Eq,
+        S: Clone,
+        C: Default,
     {
-        self.inner.get(key).filter(|x| x.not_expired())
+        let mut out = Self::with_capacity_hasher_and_clock(0, map.hasher().clone(), C::default());
+        out.insert_batch(map, ttl);
+        out
     }
 
-    /// If the value exists and has not expired, return it
-    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    /// Turn this map into a [`HashMap`] of its live values, dropping their
+    /// expiry data. Expired entries are dropped, and passed to the
+    /// [eviction listener](Self::on_evict).
+    pub fn into_hashmap(self) -> HashMap<K, V, S>
     where
-        K: Borrow<Q>,
-        Q: ?Sized + Hash + Eq,
+        S: Clone,
     {
-        // get meta checks expiry for us
-        self.get_meta(key).map(|v| &v.value)
+        let now = self.now();
+        let mut listener = self.on_evict;
+        let mut out =
+            HashMap::with_capacity_and_hasher(self.inner.len(), self.inner.hasher().clone());
+        for (key, value) in self.inner {
+            if value.not_expired_at(now) {
+                out.insert(key, value.value);
+            } else if let Some(listener) = &mut listener {
+                (listener.0)(&key, value.value);
+            }
+        }
+        out
     }
 
-    /// If a key exists for this value, get both the key and value if it is not expired
-    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    /// Turn this map into one with the result of `f` for each live value,
+    /// keeping keys, expiry times, and settings. Expired entries are dropped,
+    /// and passed to the [eviction listener](Self::on_evict), which isn't kept
+    /// since it takes the old values. Any weight limit isn't kept either.
+    pub fn map_values<U, F: FnMut(V) -> U>(self, mut f: F) -> ExpiringMap<K, U, S, C>
     where
-        K: Borrow<Q>,
-        Q: ?Sized + Hash + Eq,
+        S: Clone,
     {
-        self.inner
-            .get_key_value(key)
-            .filter(|(_, v)| v.not_expired())
-            .map(|(k, v)| (k, &v.value))
+        let now = self.now();
+        let mut listener = self.on_evict;
+        let mut inner = ExpiringMapInner::with_capacity_and_hasher(
+            self.inner.len(),
+            self.inner.hasher().clone(),
+        );
+        for (key, value) in self.inner {
+            if value.not_expired_at(now) {
+                inner.insert(key, value.map(&mut f));
+            } else if let Some(listener) = &mut listener {
+                (listener.0)(&key, value.value);
+            }
+        }
+        ExpiringMap {
+            last_size: self.last_size,
+            vacuum_factor: self.vacuum_factor,
+            min_vacuum_size: self.min_vacuum_size,
+            trigger: self.trigger,
+            shrink_policy: self.shrink_policy,
+            vacuum_on_read: self.vacuum_on_read,
+            vacuum_pending: self.vacuum_pending,
+            default_ttl: self.default_ttl,
+            ttl_policy: None,
+            sliding: self.sliding,
+            max_len: self.max_len,
+            on_evict: None,
+            index: self.index,
+            accesses: self.accesses,
+            rng: self.rng,
+            weigher: None,
+            weight: 0,
+            stats: self.stats,
+            frozen_at: self.frozen_at,
+            clock: self.clock,
+            inner,
+        }
     }
 
-    /// Get a mutable reference to the value pointed to by a key, if it is not expired
-    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    /// Insert a value into the map with the [default TTL](Self::default_ttl),
+    /// returning the old value if it has not expired and existed
+    pub fn insert_default(&mut self, key: K, value: V) -> Option<ExpiryValue<V>> {
+        self.insert(key, value, self.default_ttl)
+    }
+
+    /// Pick the TTL of entries inserted with [`Self::insert_policied`] by
+    /// calling `policy` with their key and value, such as to keep error
+    /// responses for less time than successful ones. This replaces any policy
+    /// which was already set. It is kept by clones of the map, but not by
+    /// [`Self::map_values`].
+    pub fn set_ttl_policy<F>(&mut self, policy: F)
     where
-        K: Borrow<Q>,
-        Q: ?Sized + Hash + Eq,
+        F: Fn(&K, &V) -> Duration + Send + Sync + 'static,
     {
-        self.inner
-            .get_mut(key)
-            .filter(|x| x.not_expired())
-            .map(|v| &mut v.value)
+        self.ttl_policy = Some(TtlPolicy(Arc::new(policy)));
     }
 
-    /// Insert a value into the map, returning the old value if it has not expired and existed
-    pub fn insert(&mut self, key: K, value: V, ttl: Duration) -> Option<ExpiryValue<V>> {
-        self.vacuum_if_needed();
-        let entry = ExpiryValue {
-            inserted: Instant::now(),
-            ttl,
-            value,
-        };
-        self.inner
-            .insert(key, entry)
-            .filter(ExpiryValue::not_expired)
+    /// Insert a value into the map with the TTL the
+    /// [TTL policy](Self::set_ttl_policy) picks for it, or the
+    /// [default TTL](Self::default_ttl) if there is no policy, returning the
+    /// old value if it has not expired and existed
+    pub fn insert_policied(&mut self, key: K, value: V) -> Option<ExpiryValue<V>> {
+        let ttl = self
+            .ttl_policy
+            .as_ref()
+            .map_or(self.default_ttl, |policy| (policy.0)(&key, &value));
+        self.insert(key, value, ttl)
     }
 
     /// If this key exists and is not expired, returns true
@@ -199,7 +2020,7 @@ impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V> {
         K: Borrow<Q>,
         Q: ?Sized + Hash + Eq,
     {
-        self.get_meta(key).is_some_and(ExpiryValue::not_expired)
+        self.get_meta(key).is_some()
     }
 
     /// Remove an item from the map. If it exists and has not expired, return true
@@ -208,10 +2029,176 @@ impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V> {
         K: Borrow<Q>,
         Q: ?Sized + Hash + Eq,
     {
+        let now = self.now();
+        let Some((key, value)) = self.inner.remove_entry(key) else {
+            return false;
+        };
+        self.weight -= value.weight;
+        let live = value.not_expired_at(now);
+        self.evicted(&key, value.value);
+        live
+    }
+
+    /// Remove every one of `keys` from the map, returning how many of them
+    /// were live. The clock is only read once, so every key is checked as of
+    /// the same moment.
+    pub fn remove_all<'a, Q, I>(&mut self, keys: I) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        let now = self.now();
+        let mut removed = 0;
+        for key in keys {
+            let Some((key, value)) = self.inner.remove_entry(key) else {
+                continue;
+            };
+            self.weight -= value.weight;
+            if value.not_expired_at(now) {
+                removed += 1;
+            }
+            self.evicted(&key, value.value);
+        }
+        removed
+    }
+
+    /// An iterator over every key and its expiry data which has not expired.
+    /// Expired entries are skipped, but not removed.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &ExpiryValue<V>)> {
+        self.iter_at(self.now())
+    }
+
+    /// [`Self::iter`] as of `now`, for operations which look at several
+    /// entries and need them all judged at the same moment
+    fn iter_at(&self, now: Instant) -> impl Iterator<Item = (&K, &ExpiryValue<V>)> {
         self.inner
-            .remove(key)
-            .as_ref()
-            .is_some_and(ExpiryValue::not_expired)
+            .iter()
+            .filter(move |(_, v)| v.not_expired_at(now))
+    }
+
+    /// An iterator over every value which has not expired
+    pub fn iter_values(&self) -> impl Iterator<Item = &V> {
+        self.values()
+    }
+
+    /// An iterator over every key which has not expired
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// An iterator over every value which has not expired
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| &v.value)
+    }
+
+    /// Consume the map, returning an iterator over every key which has not
+    /// expired. Expired entries are passed to the
+    /// [eviction listener](Self::on_evict), like with [`IntoIter`].
+    pub fn into_keys(self) -> impl Iterator<Item = K> {
+        self.into_iter().map(|(k, _)| k)
+    }
+
+    /// Consume the map, returning an iterator over every value which has not
+    /// expired. Expired entries are passed to the
+    /// [eviction listener](Self::on_evict), like with [`IntoIter`].
+    pub fn into_values(self) -> impl Iterator<Item = V> {
+        self.into_iter().map(|(_, v)| v)
+    }
+
+    /// An iterator over every key and a mutable reference to its value which
+    /// has not expired. Expired entries are skipped, but not removed.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        let now = self.now();
+        self.inner
+            .iter_mut()
+            .filter(move |(_, v)| v.not_expired_at(now))
+            .map(|(k, v)| (k, &mut v.value))
+    }
+
+    /// An iterator over a mutable reference to every value which has not expired
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.iter_mut().map(|(_, v)| v)
+    }
+
+    /// An iterator over every key and its expiry data which has not expired,
+    /// but will within `window`. Persistent entries are never included.
+    pub fn entries_expiring_within(
+        &self,
+        window: Duration,
+    ) -> impl Iterator<Item = (&K, &ExpiryValue<V>)> {
+        let now = self.now();
+        self.iter_at(now)
+            .filter(move |(_, v)| v.remaining_at(now) < window)
+    }
+
+    /// When the next entry which has not yet expired will expire. Returns
+    /// [`None`] if there are no live entries, or if every live entry's expiry
+    /// is too far in the future to be represented. This is O(n).
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.iter().filter_map(|(_, v)| v.deadline()).min()
+    }
+
+    /// The live entry with the least time [remaining](Self::remaining), or
+    /// [`None`] if there are no live entries. This is O(n).
+    pub fn soonest_expiring(&self) -> Option<(&K, &ExpiryValue<V>)> {
+        let now = self.now();
+        self.iter_at(now).min_by_key(|(_, v)| v.remaining_at(now))
+    }
+
+    /// The live entry with the most time [remaining](Self::remaining), or
+    /// [`None`] if there are no live entries. Persistent entries count as
+    /// expiring last. This is O(n).
+    pub fn latest_expiring(&self) -> Option<(&K, &ExpiryValue<V>)> {
+        let now = self.now();
+        self.iter_at(now).max_by_key(|(_, v)| v.remaining_at(now))
+    }
+
+    /// How long until the next entry which has not yet expired will expire.
+    /// Returns [`None`] if there are no live entries. This is O(n).
+    pub fn time_until_next_expiry(&self) -> Option<Duration> {
+        self.next_expiry()
+            .map(|deadline| deadline.saturating_duration_since(self.now()))
+    }
+
+    /// Remove every entry from the map, expired or not, keeping the allocated
+    /// memory for reuse
+    pub fn clear(&mut self) {
+        if let Some(listener) = &mut self.on_evict {
+            for (key, value) in self.inner.drain() {
+                (listener.0)(&key, value.value);
+            }
+        } else {
+            self.inner.clear();
+        }
+        if let Some(index) = &mut self.index {
+            index.clear();
+        }
+        self.weight = 0;
+        self.last_size = self.min_vacuum_size;
+    }
+
+    /// Remove every entry from the map, returning an iterator over the keys and
+    /// values of the ones which have not expired. Expired entries are dropped,
+    /// and the allocated memory is kept for reuse. If the iterator is dropped
+    /// early, the rest of the entries are still removed.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        if let Some(index) = &mut self.index {
+            index.clear();
+        }
+        self.weight = 0;
+        self.last_size = self.min_vacuum_size;
+        let now = self.now();
+        let listener = &mut self.on_evict;
+        self.inner.drain().filter_map(move |(key, value)| {
+            if value.not_expired_at(now) {
+                return Some((key, value.value));
+            }
+            if let Some(listener) = listener.as_mut() {
+                (listener.0)(&key, value.value);
+            }
+            None
+        })
     }
 
     /// Return the size the map was last time it was vacuumed
@@ -219,7 +2206,9 @@ impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V> {
         self.last_size
     }
 
-    /// Return the number of items within the map
+    /// Return the number of items within the map. This is O(1), but includes
+    /// expired entries which have not been vacuumed yet; use
+    /// [`Self::len_live`] for only the live ones.
     pub fn len(&self) -> usize {
         self.inner.len()
     }
@@ -229,14 +2218,99 @@ impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V> {
         self.inner.len() == 0
     }
 
+    /// Return the number of entries which have not expired. This is O(n).
+    pub fn len_live(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Return true if there are no entries which have not expired
+    pub fn is_empty_live(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Return the number of expired entries which have not been vacuumed yet.
+    /// This is O(n).
+    pub fn expired_count(&self) -> usize {
+        let now = self.now();
+        self.inner
+            .values()
+            .filter(|v| !v.not_expired_at(now))
+            .count()
+    }
+
+    /// Count live entries by how long they have [left](Self::remaining).
+    /// `buckets` are sorted upper bounds, and each entry is counted in the
+    /// first bucket it is no longer than, or in one extra bucket at the end if
+    /// it is longer than all of them, so persistent entries usually end up
+    /// there. This is O(n).
+    pub fn ttl_histogram(&self, buckets: &[Duration]) -> Vec<usize> {
+        let now = self.now();
+        let mut counts = vec![0; buckets.len() + 1];
+        for (_, value) in self.iter_at(now) {
+            let remaining = value.remaining_at(now);
+            counts[buckets.partition_point(|&bound| bound < remaining)] += 1;
+        }
+        counts
+    }
+
+    /// Return the number of live entries for which `f` returns true. This is
+    /// O(n).
+    pub fn count_where<F: Fn(&K, &V) -> bool>(&self, f: F) -> usize {
+        let now = self.now();
+        self.inner
+            .iter()
+            .filter(|(k, v)| v.not_expired_at(now) && f(k, &v.value))
+            .count()
+    }
+
+    /// The `n` live keys which have been [read](ExpiryValue::access_count)
+    /// the most, with their access counts, most read first. This is O(n log n),
+    /// and needs the `access-count` feature.
+    #[cfg(feature = "access-count")]
+    pub fn most_accessed(&self, n: usize) -> Vec<(&K, u64)> {
+        let mut counts: Vec<(&K, u64)> = self.iter().map(|(k, v)| (k, v.access_count())).collect();
+        counts.sort_unstable_by_key(|&(_, count)| Reverse(count));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Returns true if `key` is in the map, but has expired
+    pub fn contains_expired_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let now = self.now();
+        self.inner.get(key).is_some_and(|v| !v.not_expired_at(now))
+    }
+
     /// Return the capacity of the internal map
     pub fn capacity(&self) -> usize {
         self.inner.capacity()
     }
 
+    /// A rough estimate of how many bytes the map uses, from its
+    /// [capacity](Self::capacity) and the sizes of its keys and values. This
+    /// doesn't count anything the keys and values allocate themselves, such as
+    /// the contents of a [string](alloc::string::String), or the
+    /// [expiry index](Self::enable_expiry_index).
+    pub fn estimated_memory(&self) -> usize {
+        // hash tables keep a control byte for each slot
+        let slot = size_of::<(K, ExpiryValue<V>)>() + 1;
+        size_of::<Self>() + self.capacity() * slot
+    }
+
     /// Reserve at least a certain capacity on the internal map
-    pub fn reserve(&mut self, addtional: usize) {
-        self.inner.reserve(addtional);
+    pub fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+    }
+
+    /// [Vacuum](Self::vacuum) the map, then reserve room for at least
+    /// `additional` more entries beyond the live ones, so expired entries
+    /// aren't counted towards the new capacity. This is O(n).
+    pub fn reserve_live(&mut self, additional: usize) {
+        self.vacuum();
+        self.inner.reserve(additional);
     }
 
     /// Remove all of the expired entries and shrink the map to the minimum
@@ -246,6 +2320,21 @@ impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V> {
         self.inner.shrink_to_fit();
     }
 
+    /// Remove all of the expired entries, then move the live ones into a
+    /// freshly allocated table with just enough room for them. Unlike
+    /// [`Self::shrink_to_fit`], this always rebuilds the table, which clears
+    /// out any leftovers from heavy churn. This is O(n).
+    pub fn compact(&mut self)
+    where
+        S: Clone,
+    {
+        self.vacuum();
+        let fresh =
+            ExpiringMapInner::with_capacity_and_hasher(self.inner.len(), self.hasher().clone());
+        let old = core::mem::replace(&mut self.inner, fresh);
+        self.inner.extend(old);
+    }
+
     /// Remove all of the expired entries and shrink the map to the minimum of
     /// the minimum allowable size and the `min_capacity` in accordance with the
     /// resize policy
@@ -254,19 +2343,128 @@ impl<K: PartialEq + Eq + Hash, V> ExpiringMap<K, V> {
         self.inner.shrink_to(min_capacity);
     }
 
+    /// Removes a key from the map, returning its value if it had not expired.
+    /// This is [`Self::remove`], but keeps the value.
+    pub fn take<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
     /// Removes a key from the map, returning the stored key and value if the key was previously in the map.
     pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
     where
         K: Borrow<Q>,
         Q: ?Sized + Hash + Eq,
     {
-        self.inner
-            .remove_entry(key)
-            .filter(|(_, v)| v.not_expired())
-            .map(|(k, v)| (k, v.value))
+        let now = self.now();
+        let (key, value) = self.inner.remove_entry(key)?;
+        self.weight -= value.weight;
+        value.not_expired_at(now).then_some((key, value.value))
+    }
+
+    /// Get the entry for `key` for in-place manipulation. Expired entries
+    /// are vacant, and `ttl` is used for any value inserted through the
+    /// entry. If the map is [bounded](Self::with_max_len) and full, room is
+    /// only made for `key` once a value is inserted into the entry. `key` is
+    /// only hashed once, unless the map is full or has a
+    /// [weight limit](Self::with_max_weight), since then inserting may evict
+    /// other entries.
+    pub fn entry(&mut self, key: K, ttl: Duration) -> Entry<'_, K, V, S, C> {
+        self.vacuum_if_needed();
+        let full = self.max_len.is_some_and(|max| self.inner.len() >= max);
+        if full || self.weigher.is_some() {
+            return Entry::with_map(self, key, ttl);
+        }
+        let context = EntryContext {
+            now: self.now(),
+            sliding: self.sliding,
+            accesses: self.max_len.map(|_| &self.accesses),
+            listener: self.on_evict.as_mut(),
+            index: self.index.as_mut(),
+        };
+        Entry::new(self.inner.entry(key), ttl, context)
+    }
+
+    /// Return the value for `key` if it exists and has not expired,
+    /// otherwise insert `default` with `ttl` and return that. `key` is only
+    /// hashed once, as with [`Self::entry`].
+    pub fn get_or_insert(&mut self, key: K, default: V, ttl: Duration) -> &mut V {
+        self.entry(key, ttl).or_insert(default)
+    }
+
+    /// Return the value for `key` if it exists and has not expired,
+    /// otherwise insert the result of `f` with `ttl` and return that. `key`
+    /// is only hashed once, as with [`Self::entry`].
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, ttl: Duration, f: F) -> &mut V {
+        self.entry(key, ttl).or_insert_with(f)
+    }
+
+    /// Return the value for `key` if it exists and has not expired,
+    /// otherwise call `f`, and insert its value with `ttl` if it succeeds.
+    ///
+    /// # Errors
+    /// If `f` fails, its error is returned without inserting anything, so
+    /// failures aren't cached, and nothing is evicted to make room for them.
+    pub fn get_or_try_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        ttl: Duration,
+        f: F,
+    ) -> Result<&mut V, E> {
+        match self.entry(key, ttl) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?)),
+        }
+    }
+
+    /// Return the value for `key` if it exists and has not expired,
+    /// otherwise insert the value `f` returns with the TTL it returns, and
+    /// return that. `f` is only called on a miss.
+    pub fn get_or_insert_with_ttl<F: FnOnce() -> (V, Duration)>(&mut self, key: K, f: F) -> &mut V {
+        match self.entry(key, Duration::ZERO) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let (value, ttl) = f();
+                entry.insert_with_ttl(value, ttl)
+            }
+        }
+    }
+
+    /// Return the value for `key` if it exists and has not expired,
+    /// otherwise await `f` and insert its output with `ttl`, and return
+    /// that. `f` is only awaited on a miss.
+    ///
+    /// The map is borrowed until this finishes, so nothing else can insert
+    /// `key` while `f` runs. To avoid holding a lock on a shared map for
+    /// that long, check for the key, release the lock, await the value, and
+    /// then insert it with [`Self::get_or_insert_with`] under the lock
+    /// again. In that case, two tasks which both miss will both compute the
+    /// value, and whichever inserts first wins.
+    #[cfg(feature = "async")]
+    pub async fn get_or_insert_with_async<F: Future<Output = V>>(
+        &mut self,
+        key: K,
+        ttl: Duration,
+        f: F,
+    ) -> &mut V {
+        let now = self.now();
+        let hit = self.inner.get(&key).is_some_and(|v| v.not_expired_at(now));
+        let value = if hit { Err(f) } else { Ok(f.await) };
+        match self.entry(key, ttl) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match value {
+                Ok(value) => entry.insert(value),
+                // the entry expired while it was being looked up
+                Err(f) => entry.insert(f.await),
+            },
+        }
     }
 }
 
+#[cfg(feature = "std")]
 impl<K: PartialEq + Eq + Hash> ExpiringSet<K> {
     /// Create a new [`ExpiringSet`]
     pub fn new() -> Self {
@@ -278,18 +2476,57 @@ impl<K: PartialEq + Eq + Hash> ExpiringSet<K> {
         Self(ExpiringMap::with_capacity(capacity))
     }
 
-    /// Returns true if the set contains this value
+    /// Create a new [`ExpiringSet`] whose [`Self::insert_default`] uses `ttl`
+    pub fn with_default_ttl(ttl: Duration) -> Self {
+        Self(ExpiringMap::with_default_ttl(ttl))
+    }
+}
+
+impl<K: PartialEq + Eq + Hash, C: Clock> ExpiringSet<K, RandomState, C> {
+    /// Create a new [`ExpiringSet`] which uses `clock` to tell the time
+    pub fn with_clock(clock: C) -> Self {
+        Self(ExpiringMap::with_clock(clock))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, S> ExpiringSet<K, S> {
+    /// Create a new [`ExpiringSet`] which uses `hasher` to hash keys
+    pub fn with_hasher(hasher: S) -> Self {
+        Self(ExpiringMap::with_hasher(hasher))
+    }
+
+    /// Create a new [`ExpiringSet`] with the specified capacity which uses
+    /// `hasher` to hash keys
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        Self(ExpiringMap::with_capacity_and_hasher(capacity, hasher))
+    }
+}
+
+impl<K, S, C> ExpiringSet<K, S, C> {
+    /// Create a new [`ExpiringSet`] with the specified capacity which uses
+    /// `hasher` to hash keys and `clock` to tell the time
+    pub fn with_capacity_hasher_and_clock(capacity: usize, hasher: S, clock: C) -> Self {
+        Self(ExpiringMap::with_capacity_hasher_and_clock(
+            capacity, hasher, clock,
+        ))
+    }
+}
+
+impl<K: PartialEq + Eq + Hash, S: BuildHasher, C: Clock> ExpiringSet<K, S, C> {
+    /// Insert a value into the set, restarting its TTL if it was already
+    /// there. Returns true if the value was newly added, meaning it was
+    /// missing or had expired, like
+    /// [`HashSet::insert`](std::collections::HashSet::insert).
     pub fn insert(&mut self, key: K, ttl: Duration) -> bool {
-        self.vacuum_if_needed();
-        let entry = ExpiryValue {
-            inserted: Instant::now(),
-            ttl,
-            value: (),
-        };
-        self.inner
-            .insert(key, entry)
-            .filter(ExpiryValue::not_expired)
-            .is_some()
+        self.0.insert(key, (), ttl).is_none()
+    }
+
+    /// Insert a value into the set with the
+    /// [default TTL](ExpiringMap::default_ttl), returning true if it was newly
+    /// added
+    pub fn insert_default(&mut self, key: K) -> bool {
+        self.0.insert_default(key, ()).is_none()
     }
 
     /// Returns true if the set contains this value
@@ -302,6 +2539,46 @@ impl<K: PartialEq + Eq + Hash> ExpiringSet<K> {
         self.0.contains_key(key)
     }
 
+    /// Returns true if the set contains every one of `keys`, stopping at the
+    /// first one it doesn't. The clock is only read once, so every key is
+    /// checked as of the same moment.
+    pub fn contains_all<'a, Q, I>(&self, keys: I) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        let now = self.0.now();
+        keys.into_iter()
+            .all(|key| self.0.get_meta_at(key, now).is_some())
+    }
+
+    /// Returns true if the set contains any of `keys`, stopping at the first
+    /// one it does. The clock is only read once, so every key is checked as of
+    /// the same moment.
+    pub fn contains_any<'a, Q, I>(&self, keys: I) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        let now = self.0.now();
+        keys.into_iter()
+            .any(|key| self.0.get_meta_at(key, now).is_some())
+    }
+
+    /// An iterator over every value in the set which has not expired.
+    /// Expired values are skipped, but not removed.
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.0.keys()
+    }
+
+    /// Return the number of values in the set which have not expired. This is
+    /// O(n).
+    pub fn len_live(&self) -> usize {
+        self.0.len_live()
+    }
+
     /// If it exists and has not expired, remove and return the value at this key
     pub fn take<Q>(&mut self, key: &Q) -> Option<K>
     where
@@ -311,6 +2588,27 @@ impl<K: PartialEq + Eq + Hash> ExpiringSet<K> {
         self.0.remove_entry(key).map(|(k, ())| k)
     }
 
+    /// If it exists and has not expired, remove and return the value at this
+    /// key, but only if `pred` returns true for it. Otherwise, it is left in
+    /// the set.
+    pub fn take_if<Q, F>(&mut self, key: &Q, pred: F) -> Option<K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&K) -> bool,
+    {
+        let now = self.0.now();
+        let (stored, _) = self
+            .0
+            .inner
+            .get_key_value(key)
+            .filter(|(_, v)| v.not_expired_at(now))?;
+        if !pred(stored) {
+            return None;
+        }
+        self.take(key)
+    }
+
     /// Shrink the set to the minimum allowable size in accordance with the
     /// resize policy
     pub fn shrink_to_fit(&mut self) {
@@ -324,14 +2622,320 @@ impl<K: PartialEq + Eq + Hash> ExpiringSet<K> {
     }
 }
 
-impl<K: PartialEq + Eq + Hash, V> Default for ExpiringMap<K, V> {
+impl<K: PartialEq + Eq + Hash, S: BuildHasher, C: Clock> ExpiringSet<K, S, C> {
+    /// Returns true if every live value in this set is live in `other`
+    pub fn is_subset(&self, other: &Self) -> bool {
+        let now = self.0.now();
+        self.0
+            .iter_at(now)
+            .all(|(key, _)| other.live_at(key, now).is_some())
+    }
+
+    /// Returns true if every live value in `other` is live in this set
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Returns true if no value is live in both this set and `other`
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        let now = self.0.now();
+        self.0
+            .iter_at(now)
+            .all(|(key, _)| other.live_at(key, now).is_none())
+    }
+
+    /// the expiry data for `key`, if it is live as of `now`
+    fn live_at(&self, key: &K, now: Instant) -> Option<&ExpiryValue<()>> {
+        self.0.inner.get(key).filter(|v| v.not_expired_at(now))
+    }
+}
+
+impl<K, S, C> ExpiringSet<K, S, C>
+where
+    K: PartialEq + Eq + Hash + Clone,
+    S: BuildHasher + Clone,
+    C: Clock + Clone,
+{
+    /// A new set of the values which are live in this set, `other`, or both.
+    /// Values in both keep the longer of their remaining TTLs.
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        let now = self.0.now();
+        let mut out = self.empty_like();
+        for (key, value) in self.0.iter_at(now) {
+            let value = other
+                .live_at(key, now)
+                .filter(|o| o.remaining_at(now) > value.remaining_at(now))
+                .unwrap_or(value);
+            out.insert_copy(key, value, now);
+        }
+        for (key, value) in other.0.iter_at(now) {
+            if self.live_at(key, now).is_none() {
+                out.insert_copy(key, value, now);
+            }
+        }
+        out
+    }
+
+    /// A new set of the values which are live in both this set and `other`,
+    /// keeping the longer of their remaining TTLs
+    #[must_use]
+    pub fn intersection(&self, other: &Self) -> Self {
+        let now = self.0.now();
+        let mut out = self.empty_like();
+        for (key, value) in self.0.iter_at(now) {
+            if let Some(o) = other.live_at(key, now) {
+                let value = if o.remaining_at(now) > value.remaining_at(now) {
+                    o
+                } else {
+                    value
+                };
+                out.insert_copy(key, value, now);
+            }
+        }
+        out
+    }
+
+    /// A new set of the values which are live in this set, but not in `other`
+    #[must_use]
+    pub fn difference(&self, other: &Self) -> Self {
+        self.difference_at(other, self.0.now())
+    }
+
+    /// [`Self::difference`] as of `now`
+    fn difference_at(&self, other: &Self, now: Instant) -> Self {
+        let mut out = self.empty_like();
+        for (key, value) in self.0.iter_at(now) {
+            if other.live_at(key, now).is_none() {
+                out.insert_copy(key, value, now);
+            }
+        }
+        out
+    }
+
+    /// A new set of the values which are live in exactly one of this set and
+    /// `other`
+    #[must_use]
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        let now = self.0.now();
+        let mut out = self.difference_at(other, now);
+        for (key, value) in other.0.iter_at(now) {
+            if self.live_at(key, now).is_none() {
+                out.insert_copy(key, value, now);
+            }
+        }
+        out
+    }
+
+    /// an empty set with the same hasher and clock as this one
+    fn empty_like(&self) -> Self {
+        Self::with_capacity_hasher_and_clock(0, self.hasher().clone(), self.0.clock.clone())
+    }
+
+    /// insert `key` with the time `value` has left as of `now`
+    fn insert_copy(&mut self, key: &K, value: &ExpiryValue<()>, now: Instant) {
+        let ttl = value.ttl.map(|_| value.remaining_at(now));
+        self.0.insert_at(key.clone(), (), ttl, now);
+    }
+}
+
+impl<K, V, S: Default, C: Default> Default for ExpiringMap<K, V, S, C> {
     fn default() -> Self {
-        Self::new()
+        Self::with_capacity_hasher_and_clock(0, S::default(), C::default())
     }
 }
 
-impl<K: PartialEq + Eq + Hash> Default for ExpiringSet<K> {
+impl<K, S: Default, C: Default> Default for ExpiringSet<K, S, C> {
     fn default() -> Self {
-        Self::new()
+        Self::with_capacity_hasher_and_clock(0, S::default(), C::default())
+    }
+}
+
+/// Shows how many entries are live, how many have expired but not been
+/// vacuumed, and the capacity. The alternate form, `{:#?}`, lists every entry
+/// too.
+impl<K: Debug, V: Debug, S, C: Clock> Debug for ExpiringMap<K, V, S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let now = self.now();
+        let live = self
+            .inner
+            .values()
+            .filter(|v| v.not_expired_at(now))
+            .count();
+        let alternate = f.alternate();
+        let mut out = f.debug_struct("ExpiringMap");
+        out.field("live", &live)
+            .field("expired_pending", &(self.inner.len() - live))
+            .field("capacity", &self.inner.capacity());
+        if alternate {
+            out.field("entries", &self.inner);
+        }
+        out.finish_non_exhaustive()
+    }
+}
+
+impl<K: Debug, S, C: Clock> Debug for ExpiringSet<K, S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ExpiringSet").field(&self.0).finish()
+    }
+}
+
+/// Maps are equal if they have the same live keys and values. Expired entries
+/// and TTLs are ignored, so entries which expire at different times can still
+/// be equal.
+impl<K, V, S, C> PartialEq for ExpiringMap<K, V, S, C>
+where
+    K: PartialEq + Eq + Hash,
+    V: PartialEq,
+    S: BuildHasher,
+    C: Clock,
+{
+    fn eq(&self, other: &Self) -> bool {
+        let now = other.now();
+        let ours = self.now();
+        self.iter_at(ours).count() == other.iter_at(now).count()
+            && self.iter_at(ours).all(|(key, value)| {
+                other
+                    .inner
+                    .get(key)
+                    .is_some_and(|o| o.not_expired_at(now) && o.value == value.value)
+            })
+    }
+}
+
+impl<K, V, S, C> Eq for ExpiringMap<K, V, S, C>
+where
+    K: PartialEq + Eq + Hash,
+    V: Eq,
+    S: BuildHasher,
+    C: Clock,
+{
+}
+
+/// Sets are equal if they have the same live values, regardless of their TTLs
+impl<K, S, C> PartialEq for ExpiringSet<K, S, C>
+where
+    K: PartialEq + Eq + Hash,
+    S: BuildHasher,
+    C: Clock,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<K, S, C> Eq for ExpiringSet<K, S, C>
+where
+    K: PartialEq + Eq + Hash,
+    S: BuildHasher,
+    C: Clock,
+{
+}
+
+impl<K, Q, V, S, C> Index<&Q> for ExpiringMap<K, V, S, C>
+where
+    K: PartialEq + Eq + Hash + Borrow<Q>,
+    Q: ?Sized + Hash + Eq,
+    S: BuildHasher,
+    C: Clock,
+{
+    type Output = V;
+
+    /// Returns a reference to the value for `key`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not present in the map, or has expired
+    fn index(&self, key: &Q) -> &Self::Output {
+        self.get(key).expect("key is missing or expired")
+    }
+}
+
+impl<K, Q, V, S, C> IndexMut<&Q> for ExpiringMap<K, V, S, C>
+where
+    K: PartialEq + Eq + Hash + Borrow<Q>,
+    Q: ?Sized + Hash + Eq,
+    S: BuildHasher,
+    C: Clock,
+{
+    /// Returns a mutable reference to the value for `key`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not present in the map, or has expired
+    fn index_mut(&mut self, key: &Q) -> &mut Self::Output {
+        self.get_mut(key).expect("key is missing or expired")
+    }
+}
+
+impl<K, V, S, C: Clock> IntoIterator for ExpiringMap<K, V, S, C> {
+    type IntoIter = IntoIter<K, V>;
+    type Item = (K, V);
+
+    fn into_iter(self) -> Self::IntoIter {
+        let now = self.now();
+        IntoIter::new(self.inner.into_iter(), self.on_evict, now)
+    }
+}
+
+impl<'a, K, V, S, C: Clock> IntoIterator for &'a ExpiringMap<K, V, S, C> {
+    type IntoIter = Iter<'a, K, V>;
+    type Item = (&'a K, &'a V);
+
+    fn into_iter(self) -> Self::IntoIter {
+        Iter::new(self.inner.iter(), self.now())
+    }
+}
+
+impl<K, V, S, C> Extend<(K, V, Duration)> for ExpiringMap<K, V, S, C>
+where
+    K: PartialEq + Eq + Hash,
+    S: BuildHasher,
+    C: Clock,
+{
+    fn extend<T: IntoIterator<Item = (K, V, Duration)>>(&mut self, iter: T) {
+        self.vacuum_if_needed();
+        let now = self.now();
+        for (key, value, ttl) in iter {
+            self.insert_at(key, value, Some(ttl), now);
+        }
+    }
+}
+
+impl<K, V, S, C> FromIterator<(K, V, Duration)> for ExpiringMap<K, V, S, C>
+where
+    K: PartialEq + Eq + Hash,
+    S: BuildHasher + Default,
+    C: Clock + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V, Duration)>>(iter: T) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, S, C> Extend<(K, Duration)> for ExpiringSet<K, S, C>
+where
+    K: PartialEq + Eq + Hash,
+    S: BuildHasher,
+    C: Clock,
+{
+    fn extend<T: IntoIterator<Item = (K, Duration)>>(&mut self, iter: T) {
+        self.0
+            .extend(iter.into_iter().map(|(key, ttl)| (key, (), ttl)));
+    }
+}
+
+impl<K, S, C> FromIterator<(K, Duration)> for ExpiringSet<K, S, C>
+where
+    K: PartialEq + Eq + Hash,
+    S: BuildHasher + Default,
+    C: Clock + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, Duration)>>(iter: T) -> Self {
+        let mut set = Self::default();
+        set.extend(iter);
+        set
     }
 }
@@ -0,0 +1,56 @@
+use std::{
+    hash::{BuildHasher, Hash},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    task::{self, JoinHandle},
+    time::{self, MissedTickBehavior},
+};
+
+use crate::{Clock, ExpiringMap};
+
+/// Spawn a task onto the current [Tokio](tokio) runtime which
+/// [vacuums](ExpiringMap::vacuum) `map` every `interval`.
+///
+/// The task only holds a weak reference, so it stops once every [`Arc`] to
+/// the map has been dropped, or if the lock is poisoned. Each vacuum takes
+/// the lock and runs on Tokio's blocking thread pool with
+/// [`spawn_blocking`](tokio::task::spawn_blocking), so a large map doesn't
+/// stall other tasks while it is scanned. Because it runs on the runtime, the
+/// map's `K`, `V`, hasher, and clock must be `Send + 'static`.
+///
+/// # Panics
+///
+/// Panics if `interval` is zero, or if this is called outside of a Tokio
+/// runtime.
+pub fn spawn_reaper<K, V, S, C>(
+    map: &Arc<Mutex<ExpiringMap<K, V, S, C>>>,
+    interval: Duration,
+) -> JoinHandle<()>
+where
+    K: PartialEq + Eq + Hash + Send + 'static,
+    V: Send + 'static,
+    S: BuildHasher + Send + 'static,
+    C: Clock + Send + 'static,
+{
+    let weak = Arc::downgrade(map);
+    let mut ticker = time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    tokio::spawn(async move {
+        loop {
+            ticker.tick().await;
+            let Some(map) = weak.upgrade() else {
+                break;
+            };
+            // vacuuming is O(n) under a blocking lock, so keep it off the
+            // runtime's worker threads
+            let vacuumed =
+                task::spawn_blocking(move || map.lock().map(|mut map| map.vacuum()).is_ok());
+            if !matches!(vacuumed.await, Ok(true)) {
+                break;
+            }
+        }
+    })
+}
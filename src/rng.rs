@@ -0,0 +1,41 @@
+use core::{
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use crate::collections::RandomState;
+
+/// A tiny, non-cryptographic random number generator, so jitter doesn't need
+/// a dependency. See <https://prng.di.unimi.it/splitmix64.c>.
+#[derive(Debug, Clone)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub const fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// seed from the random keys generated for each [`RandomState`]
+    pub fn from_entropy() -> Self {
+        Self::new(RandomState::default().build_hasher().finish())
+    }
+
+    pub const fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// a uniformly random duration from `low` up to `low + span`, where
+    /// `span` is capped at about 584 years
+    pub fn duration_from(&mut self, low: Duration, span: Duration) -> Duration {
+        let span = u128::from(u64::try_from(span.as_nanos()).unwrap_or(u64::MAX));
+        // multiply-shift instead of modulo, so there's no bias worth noting
+        let offset = (u128::from(self.next_u64()) * (span + 1)) >> 64;
+        low.saturating_add(Duration::from_nanos(
+            u64::try_from(offset).unwrap_or(u64::MAX),
+        ))
+    }
+}
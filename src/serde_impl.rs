@@ -0,0 +1,145 @@
+//! [`serde`] support for [`ExpiringMap`], [`ExpiringSet`], and [`ExpiryValue`].
+//!
+//! An [`Instant`] can't be serialized, so each entry is stored with how long it
+//! had left when it was serialized, and gets a fresh insertion time when it is
+//! deserialized. This means entries live slightly longer than they would have
+//! in the original map, by however long they spent serialized. Expired entries
+//! are skipped, and settings such as the default TTL are not stored.
+
+use std::{
+    fmt,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    time::Duration,
+};
+
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{time::Instant, Clock, ExpiringMap, ExpiringSet, ExpiryValue};
+
+#[derive(Serialize)]
+struct SerializeExpiry<'a, T> {
+    value: &'a T,
+    remaining: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+struct DeserializeExpiry<T> {
+    value: T,
+    remaining: Option<Duration>,
+}
+
+impl<T: Serialize> Serialize for ExpiryValue<T> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        SerializeExpiry {
+            value: &self.value,
+            remaining: self.ttl.map(|_| self.remaining()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for ExpiryValue<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let DeserializeExpiry { value, remaining } = DeserializeExpiry::deserialize(deserializer)?;
+        Ok(Self::new(value, remaining, Instant::now()))
+    }
+}
+
+impl<K, V, S, C> Serialize for ExpiringMap<K, V, S, C>
+where
+    K: Serialize + Eq + Hash,
+    V: Serialize,
+    S: BuildHasher,
+    C: Clock,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let now = self.now();
+        // collect first, so formats which need the length up front get it
+        let live: Vec<_> = self
+            .inner
+            .iter()
+            .filter(|(_, v)| v.not_expired_at(now))
+            .collect();
+        let mut map = serializer.serialize_map(Some(live.len()))?;
+        for (key, value) in live {
+            let value = SerializeExpiry {
+                value: &value.value,
+                remaining: value.ttl.map(|_| value.remaining_at(now)),
+            };
+            map.serialize_entry(key, &value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, K, V, S, C> Deserialize<'de> for ExpiringMap<K, V, S, C>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+    C: Clock + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+}
+
+type MapMarker<K, V, S, C> = PhantomData<fn() -> ExpiringMap<K, V, S, C>>;
+
+struct MapVisitor<K, V, S, C>(MapMarker<K, V, S, C>);
+
+impl<'de, K, V, S, C> Visitor<'de> for MapVisitor<K, V, S, C>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+    C: Clock + Default,
+{
+    type Value = ExpiringMap<K, V, S, C>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        // don't trust the size hint too far, it comes from the input
+        let capacity = access.size_hint().unwrap_or(0).min(4096);
+        let mut map =
+            ExpiringMap::with_capacity_hasher_and_clock(capacity, S::default(), C::default());
+        let now = map.now();
+        while let Some((key, value)) = access.next_entry::<K, DeserializeExpiry<V>>()? {
+            let DeserializeExpiry { value, remaining } = value;
+            map.inner
+                .insert(key, ExpiryValue::new(value, remaining, now));
+        }
+        map.update_last_size();
+        Ok(map)
+    }
+}
+
+impl<K, S, C> Serialize for ExpiringSet<K, S, C>
+where
+    K: Serialize + Eq + Hash,
+    S: BuildHasher,
+    C: Clock,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, K, S, C> Deserialize<'de> for ExpiringSet<K, S, C>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    S: BuildHasher + Default,
+    C: Clock + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ExpiringMap::deserialize(deserializer).map(Self)
+    }
+}
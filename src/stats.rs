@@ -0,0 +1,113 @@
+use core::sync::atomic::Ordering;
+
+use crate::{atomic::AtomicU64, time::Instant, ExpiryValue};
+
+/// A snapshot of how an [`ExpiringMap`](crate::ExpiringMap) has been used,
+/// from [`ExpiringMap::stats`](crate::ExpiringMap::stats)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// Reads which found a live value
+    pub hits: u64,
+    /// Reads which found nothing, or an expired value
+    pub misses: u64,
+    /// Reads which found an expired value. These are also counted as misses.
+    pub expired: u64,
+    /// Entries removed to make room in a map with a length or weight limit
+    pub evictions: u64,
+}
+
+/// A map's counters behind [`CacheStats`], which only exist once they are
+/// [enabled](crate::ExpiringMap::enable_stats), so maps which don't use them
+/// don't pay for them
+#[derive(Debug, Clone, Default)]
+pub struct Stats(Option<Counters>);
+
+impl Stats {
+    pub fn enable(&mut self) {
+        self.0.get_or_insert_with(Counters::default);
+    }
+
+    /// count a read which found `value` if stats are enabled, returning if it
+    /// was live
+    pub fn read<V>(&self, value: Option<&ExpiryValue<V>>, now: Instant) -> bool {
+        self.0.as_ref().map_or_else(
+            || value.is_some_and(|value| value.not_expired_at(now)),
+            |counters| counters.read(value, now),
+        )
+    }
+
+    pub fn evicted(&self) {
+        if let Some(counters) = &self.0 {
+            counters.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> Option<CacheStats> {
+        self.0.as_ref().map(Counters::snapshot)
+    }
+
+    pub fn reset(&self) {
+        if let Some(counters) = &self.0 {
+            counters.reset();
+        }
+    }
+}
+
+/// The counters behind [`CacheStats`], which are atomic so reads through a
+/// shared reference can update them
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    expired: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Counters {
+    /// count a read which found `value`, returning if it was live
+    fn read<V>(&self, value: Option<&ExpiryValue<V>>, now: Instant) -> bool {
+        match value {
+            Some(value) if value.not_expired_at(now) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Some(_) => {
+                self.expired.fetch_add(1, Ordering::Relaxed);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    fn snapshot(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.expired.store(0, Ordering::Relaxed);
+        self.evictions.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Clone for Counters {
+    fn clone(&self) -> Self {
+        let stats = self.snapshot();
+        Self {
+            hits: AtomicU64::new(stats.hits),
+            misses: AtomicU64::new(stats.misses),
+            expired: AtomicU64::new(stats.expired),
+            evictions: AtomicU64::new(stats.evictions),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt::{self, Debug, Formatter},
+    hash::{BuildHasher, Hash},
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Duration,
+};
+
+use crate::{Clock, ExpiringMap, SystemClock};
+
+/// An [`ExpiringMap`] behind a [`RwLock`], which can be shared between
+/// threads. Reads take a read lock, and everything else takes a write lock.
+///
+/// A panic while the lock is held does not poison this map, since it is
+/// always left in a usable state.
+#[derive(Default)]
+pub struct SyncExpiringMap<K, V, S = RandomState, C = SystemClock>(RwLock<ExpiringMap<K, V, S, C>>);
+
+impl<K: Debug, V: Debug, S, C: Clock> Debug for SyncExpiringMap<K, V, S, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SyncExpiringMap").field(&self.0).finish()
+    }
+}
+
+impl<K: PartialEq + Eq + Hash, V> SyncExpiringMap<K, V> {
+    /// Create a new [`SyncExpiringMap`]
+    pub fn new() -> Self {
+        Self::from(ExpiringMap::new())
+    }
+}
+
+impl<K, V, S, C> SyncExpiringMap<K, V, S, C> {
+    /// Lock the map for reading
+    pub fn read(&self) -> RwLockReadGuard<'_, ExpiringMap<K, V, S, C>> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lock the map for writing
+    pub fn write(&self) -> RwLockWriteGuard<'_, ExpiringMap<K, V, S, C>> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Take the map out of its lock
+    pub fn into_inner(self) -> ExpiringMap<K, V, S, C> {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K: PartialEq + Eq + Hash, V, S: BuildHasher, C: Clock> SyncExpiringMap<K, V, S, C> {
+    /// If the value exists and has not expired, return a clone of it. With
+    /// [`ExpiringMap::vacuum_on_read`] set, this takes the write lock to vacuum
+    /// after finding an expired entry.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        let (value, pending) = {
+            let map = self.read();
+            (map.get(key).cloned(), map.vacuum_pending())
+        };
+        if pending {
+            self.write().vacuum_if_needed();
+        }
+        value
+    }
+
+    /// Insert a value into the map, returning the old value if it has not
+    /// expired and existed
+    pub fn insert(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.write().insert(key, value, ttl).map(|old| old.value)
+    }
+
+    /// Remove an item from the map, returning its value if it had not expired
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.write().take(key)
+    }
+
+    /// Remove every expired entry, returning how many were removed. This is
+    /// O(n), and holds the write lock the whole time.
+    pub fn vacuum(&self) -> usize {
+        self.write().vacuum()
+    }
+}
+
+impl<K, V, S, C> From<ExpiringMap<K, V, S, C>> for SyncExpiringMap<K, V, S, C> {
+    fn from(map: ExpiringMap<K, V, S, C>) -> Self {
+        Self(RwLock::new(map))
+    }
+}
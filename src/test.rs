@@ -1,6 +1,11 @@
 use std::{thread::sleep, time::Duration};
 
-use crate::{ExpiringMap, ExpiringSet};
+#[cfg(not(feature = "wasm"))]
+use crate::CoarseClock;
+use crate::{
+    Clock, Entry, ExpiringMap, ExpiringMapBuilder, ExpiringSet, GrowthTrigger, MockClock,
+    TimeTrigger, VacuumTrigger,
+};
 #[test]
 fn map_works() {
     let mut m = ExpiringMap::new();
@@ -45,7 +50,7 @@ fn vacuum_sweeps() {
     let mut m = ExpiringSet::new();
     m.insert("v", Duration::from_millis(50));
     sleep(Duration::from_millis(75));
-    m.vacuum();
+    assert_eq!(m.vacuum(), 1);
     assert!(!m.inner.contains_key(&"v"));
 }
 
@@ -79,3 +84,1812 @@ fn test_borrow() {
     assert!(m.contains("x"));
     assert!(m.contains(&String::from("x")));
 }
+
+#[test]
+fn default_ttl() {
+    let mut m = ExpiringMap::with_default_ttl(Duration::from_millis(50));
+    assert_eq!(m.default_ttl(), Duration::from_millis(50));
+    m.insert_default("v", "x");
+    assert_eq!(m.get_meta(&"v").unwrap().ttl(), Duration::from_millis(50));
+    m.set_default_ttl(Duration::from_secs(5));
+    m.insert_default("w", "y");
+    assert_eq!(m.get_meta(&"w").unwrap().ttl(), Duration::from_secs(5));
+    sleep(Duration::from_millis(75));
+    assert!(!m.contains_key(&"v"));
+    assert_eq!(m.get(&"w"), Some(&"y"));
+}
+
+#[test]
+fn iter_skips_expired() {
+    let mut m = ExpiringMap::new();
+    m.insert("v", 1, Duration::from_secs(5));
+    m.insert("w", 2, Duration::ZERO);
+    let live: Vec<_> = m.iter().map(|(k, v)| (*k, **v)).collect();
+    assert_eq!(live, [("v", 1)]);
+    assert_eq!(m.iter_values().collect::<Vec<_>>(), [&1]);
+    assert_eq!(m.len(), 2);
+}
+
+#[test]
+fn entry_api() {
+    let mut m = ExpiringMap::new();
+    *m.entry("v", Duration::from_secs(5)).or_insert(1) += 1;
+    assert_eq!(m.get(&"v"), Some(&2));
+    m.entry("v", Duration::from_secs(5))
+        .and_modify(|v| *v += 1)
+        .or_insert_with(|| unreachable!());
+    assert_eq!(m.get(&"v"), Some(&3));
+
+    m.insert("w", 10, Duration::ZERO);
+    assert!(matches!(
+        m.entry("w", Duration::from_secs(5)),
+        Entry::Vacant(_)
+    ));
+    m.entry("w", Duration::from_secs(5))
+        .and_modify(|_| unreachable!())
+        .or_insert(1);
+    assert_eq!(m.get(&"w"), Some(&1));
+    assert_eq!(m.get_meta(&"w").unwrap().ttl(), Duration::from_secs(5));
+}
+
+#[test]
+fn next_expiry() {
+    let mut m = ExpiringMap::new();
+    assert!(m.next_expiry().is_none());
+    m.insert("v", 1, Duration::ZERO);
+    assert!(m.next_expiry().is_none());
+    m.insert("w", 2, Duration::from_secs(50));
+    m.insert("x", 3, Duration::from_secs(5));
+    let expected = m.get_meta(&"x").unwrap().inserted() + Duration::from_secs(5);
+    assert_eq!(m.next_expiry(), Some(expected));
+    let until = m.time_until_next_expiry().unwrap();
+    assert!(until <= Duration::from_secs(5));
+    assert!(until > Duration::from_secs(4));
+}
+
+#[test]
+fn vacuum_if_needed_counts() {
+    let mut m = ExpiringMap::new();
+    assert_eq!(m.vacuum_if_needed(), None);
+    for i in 0..13 {
+        m.inner.insert(
+            i,
+            crate::ExpiryValue::new((), Some(Duration::ZERO), crate::time::Instant::now()),
+        );
+    }
+    assert_eq!(m.vacuum_if_needed(), Some(13));
+    assert!(m.is_empty());
+}
+
+#[test]
+fn drain_expired() {
+    let mut m = ExpiringMap::new();
+    m.insert("v", 1, Duration::ZERO);
+    m.insert("w", 2, Duration::from_secs(5));
+    assert_eq!(m.drain_expired(), [("v", 1)]);
+    assert_eq!(m.len(), 1);
+    assert!(m.drain_expired().is_empty());
+}
+
+#[test]
+fn get_refresh() {
+    let mut m = ExpiringMap::new();
+    m.insert("v", 1, Duration::from_millis(50));
+    m.insert("w", 2, Duration::ZERO);
+    sleep(Duration::from_millis(30));
+    assert_eq!(m.get_refresh(&"v"), Some(&1));
+    assert_eq!(m.get_refresh(&"w"), None);
+    sleep(Duration::from_millis(30));
+    assert_eq!(m.get(&"v"), Some(&1));
+    assert_eq!(m.get(&"w"), None);
+}
+
+#[test]
+fn sliding() {
+    let mut m = ExpiringMap::new();
+    m.set_sliding(true);
+    assert!(m.sliding());
+    m.insert("v", 1, Duration::from_millis(50));
+    for _ in 0..3 {
+        sleep(Duration::from_millis(30));
+        assert!(m.contains_key(&"v"));
+    }
+    sleep(Duration::from_millis(75));
+    assert!(m.get(&"v").is_none());
+}
+
+#[test]
+fn persistent() {
+    let mut m = ExpiringMap::new();
+    m.insert_persistent("v", 1);
+    m.insert("w", 2, Duration::ZERO);
+    let meta = m.get_meta(&"v").unwrap();
+    assert!(meta.is_persistent());
+    assert!(meta.not_expired());
+    assert_eq!(meta.remaining(), Duration::MAX);
+    assert!(m.next_expiry().is_none());
+    assert_eq!(m.vacuum(), 1);
+    assert_eq!(m.get(&"v"), Some(&1));
+}
+
+#[test]
+fn clear() {
+    let mut m = ExpiringSet::new();
+    for i in 0..20 {
+        m.insert(i, Duration::from_secs(5));
+    }
+    m.vacuum();
+    assert_eq!(m.last_size(), 20);
+    m.clear();
+    assert!(m.is_empty());
+    assert_eq!(m.last_size(), 8);
+}
+
+#[test]
+fn custom_hasher() {
+    use std::hash::BuildHasherDefault;
+
+    type Hasher = BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+    let mut m: ExpiringMap<String, usize, Hasher> = ExpiringMap::default();
+    m.insert(String::from("x"), 1, Duration::from_secs(5));
+    assert_eq!(m.get("x"), Some(&1));
+
+    let mut m = ExpiringSet::with_capacity_and_hasher(4, Hasher::default());
+    m.insert("x", Duration::from_secs(5));
+    assert!(m.contains("x"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip() {
+    let mut m = ExpiringMap::new();
+    m.insert(String::from("v"), 1, Duration::from_secs(5));
+    m.insert(String::from("w"), 2, Duration::ZERO);
+    m.insert_persistent(String::from("x"), 3);
+    let json = serde_json::to_string(&m).unwrap();
+    let m: ExpiringMap<String, i32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(m.len(), 2);
+    let meta = m.get_meta("v").unwrap();
+    assert!(meta.ttl() <= Duration::from_secs(5));
+    assert!(meta.ttl() > Duration::from_secs(4));
+    assert!(m.get_meta("x").unwrap().is_persistent());
+}
+
+#[test]
+fn mock_clock() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("v", 1, Duration::from_secs(10));
+    clock.advance(Duration::from_secs(9));
+    assert_eq!(m.get(&"v"), Some(&1));
+    assert_eq!(m.time_until_next_expiry(), Some(Duration::from_secs(1)));
+    clock.advance(Duration::from_secs(1));
+    assert!(m.get(&"v").is_none());
+    assert_eq!(m.vacuum(), 1);
+}
+
+#[test]
+fn mock_clock_saturates() {
+    let clock = MockClock::new();
+    clock.advance(Duration::MAX);
+    let end = clock.now();
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(clock.now(), end);
+}
+
+#[test]
+fn lru_eviction() {
+    let mut m = ExpiringMap::with_max_len(2);
+    assert_eq!(m.max_len(), Some(2));
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(5));
+    assert_eq!(m.get(&"a"), Some(&1));
+    assert_eq!(
+        m.insert_evicting("c", 3, Duration::from_secs(5)),
+        Some(("b", 2))
+    );
+    assert_eq!(m.len(), 2);
+    // replacing an existing key doesn't evict anything
+    assert_eq!(m.insert_evicting("a", 4, Duration::from_secs(5)), None);
+    *m.entry("d", Duration::from_secs(5)).or_insert(0) += 1;
+    assert!(m.get(&"c").is_none());
+    assert_eq!(m.get(&"d"), Some(&1));
+}
+
+#[test]
+fn lru_prefers_expired() {
+    let mut m = ExpiringMap::with_max_len(2);
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::ZERO);
+    assert_eq!(m.insert_evicting("c", 3, Duration::from_secs(5)), None);
+    assert_eq!(m.get(&"a"), Some(&1));
+    assert_eq!(m.get(&"c"), Some(&3));
+    assert_eq!(m.len(), 2);
+}
+
+#[test]
+fn eviction_listener() {
+    use std::sync::{Arc, Mutex};
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let mut m = ExpiringMap::with_max_len(2);
+    let log = Arc::clone(&evicted);
+    m.on_evict(move |k, v| log.lock().unwrap().push((*k, v)));
+    m.insert("a", 1, Duration::ZERO);
+    m.insert("b", 2, Duration::from_secs(5));
+    m.vacuum();
+    m.insert("c", 3, Duration::from_secs(5));
+    m.insert("d", 4, Duration::from_secs(5));
+    m.remove(&"c");
+    m.insert("e", 5, Duration::ZERO);
+    m.entry("e", Duration::from_secs(5)).or_insert(6);
+    m.clear();
+    let mut evicted = evicted.lock().unwrap().clone();
+    evicted[4..].sort_unstable();
+    assert_eq!(
+        evicted,
+        [("a", 1), ("b", 2), ("c", 3), ("e", 5), ("d", 4), ("e", 6)]
+    );
+}
+
+#[test]
+fn vacuum_due() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(1));
+    m.enable_expiry_index();
+    m.insert("b", 2, Duration::from_secs(2));
+    m.insert("c", 3, Duration::from_secs(1));
+    // overwriting leaves a stale record behind, which must not remove "c"
+    m.insert("c", 4, Duration::from_secs(3));
+    m.entry("d", Duration::from_secs(1)).or_insert(5);
+    m.insert_persistent("e", 6);
+    m.set_sliding(true);
+    clock.advance(Duration::from_millis(500));
+    assert!(m.contains_key(&"a"));
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(m.vacuum_due(), 1);
+    assert!(m.inner.contains_key(&"a"));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.vacuum_due(), 2);
+    assert!(!m.inner.contains_key(&"a"));
+    assert!(!m.inner.contains_key(&"b"));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.vacuum_due(), 1);
+    assert_eq!(m.len(), 1);
+}
+
+#[test]
+fn peek_ignores_expiry() {
+    let mut m = ExpiringMap::new();
+    m.insert("v", 1, Duration::ZERO);
+    assert!(m.get(&"v").is_none());
+    assert_eq!(m.peek(&"v"), Some(&1));
+    assert!(m.peek_meta(&"v").unwrap().expired());
+    assert!(m.peek(&"w").is_none());
+}
+
+#[test]
+fn collect() {
+    let items = [("a", 1, Duration::from_secs(5)), ("b", 2, Duration::ZERO)];
+    let mut m: ExpiringMap<_, _> = items.into_iter().collect();
+    assert_eq!(m.get(&"a"), Some(&1));
+    assert!(m.get(&"b").is_none());
+    m.extend([("c", 3, Duration::from_secs(5))]);
+    assert_eq!(m.get(&"c"), Some(&3));
+
+    let s: ExpiringSet<_> = [("a", Duration::from_secs(5)), ("b", Duration::ZERO)]
+        .into_iter()
+        .collect();
+    assert!(s.contains(&"a"));
+    assert!(!s.contains(&"b"));
+}
+
+#[test]
+fn get_or_insert_with() {
+    let mut m = ExpiringMap::new();
+    *m.get_or_insert_with("v", Duration::from_secs(5), || 1) += 1;
+    assert_eq!(*m.get_or_insert_with("v", Duration::from_secs(5), || 10), 2);
+    m.insert("w", 1, Duration::ZERO);
+    assert_eq!(
+        *m.get_or_insert_with("w", Duration::from_secs(5), || 10),
+        10
+    );
+}
+
+#[test]
+fn change_ttl() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(2));
+    m.insert("b", 2, Duration::from_secs(2));
+    m.insert("c", 3, Duration::from_secs(2));
+    assert!(m.extend_ttl(&"a", Duration::from_secs(2)));
+    assert!(m.set_ttl(&"b", Duration::from_secs(3)));
+    clock.advance(Duration::from_secs(1));
+    assert!(m.renew(&"c"));
+    assert!(!m.renew(&"d"));
+    clock.advance(Duration::from_millis(1500));
+    assert!(m.contains_key(&"a"));
+    assert!(m.contains_key(&"b"));
+    assert!(m.contains_key(&"c"));
+    clock.advance(Duration::from_secs(1));
+    assert!(m.contains_key(&"a"));
+    assert!(!m.contains_key(&"b"));
+    assert!(!m.extend_ttl(&"b", Duration::from_secs(5)));
+    assert!(!m.set_ttl(&"b", Duration::from_secs(5)));
+    assert!(!m.renew(&"b"));
+    assert!(!m.contains_key(&"b"));
+}
+
+#[test]
+fn insert_until() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    let deadline = clock.now() + Duration::from_secs(2);
+    m.insert_until("a", 1, deadline);
+    m.insert_until("b", 2, clock.now());
+    assert_eq!(m.expires_at(&"a"), Some(deadline));
+    assert!(m.get(&"b").is_none());
+    assert!(m.expires_at(&"b").is_none());
+    clock.advance(Duration::from_secs(2));
+    assert!(m.get(&"a").is_none());
+}
+
+#[test]
+fn len_live() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(3));
+    assert_eq!(m.len_live(), 2);
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.len(), 2);
+    assert_eq!(m.len_live(), 1);
+    assert!(!m.is_empty_live());
+    clock.advance(Duration::from_secs(2));
+    assert!(m.is_empty_live());
+    assert!(!m.is_empty());
+}
+
+#[test]
+fn clone() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(2));
+    clock.advance(Duration::from_secs(1));
+    let copy = m.clone();
+    assert_eq!(copy.expires_at(&"a"), m.expires_at(&"a"));
+    assert_eq!(copy.last_size(), m.last_size());
+    clock.advance(Duration::from_secs(1));
+    assert!(copy.get(&"a").is_none());
+
+    let mut s = ExpiringSet::new();
+    s.insert("a", Duration::from_secs(5));
+    assert!(s.clone().contains(&"a"));
+}
+
+#[test]
+fn set_algebra() {
+    let clock = MockClock::new();
+    let mut a = ExpiringSet::with_clock(clock.clone());
+    let mut b = ExpiringSet::with_clock(clock.clone());
+    a.insert(1, Duration::from_secs(1));
+    a.insert(2, Duration::from_secs(5));
+    a.insert(3, Duration::from_secs(1));
+    b.insert(1, Duration::from_secs(5));
+    b.insert(4, Duration::from_secs(5));
+    b.insert(3, Duration::ZERO);
+
+    let union = a.union(&b);
+    assert_eq!(union.len_live(), 4);
+    let intersection = a.intersection(&b);
+    assert_eq!(intersection.len_live(), 1);
+    assert!(a.difference(&b).contains(&3));
+    assert!(!a.difference(&b).contains(&1));
+    let sym = a.symmetric_difference(&b);
+    assert!(sym.contains(&2) && sym.contains(&3) && sym.contains(&4));
+    assert!(!sym.contains(&1));
+    assert!(intersection.is_subset(&a));
+    assert!(union.is_superset(&b));
+    assert!(!a.is_disjoint(&b));
+
+    // 1 keeps the longer of its two TTLs
+    clock.advance(Duration::from_secs(2));
+    assert!(union.contains(&1));
+    assert!(intersection.contains(&1));
+    assert!(!union.contains(&3));
+}
+
+#[test]
+fn retain() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("ok", 200, Duration::from_secs(5));
+    m.insert("err", 503, Duration::from_secs(5));
+    m.insert("old", 200, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    m.retain(|_, status| *status < 500);
+    assert_eq!(m.len(), 1);
+    assert_eq!(m.get(&"ok"), Some(&200));
+}
+
+#[test]
+fn iter_mut() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    for v in m.values_mut() {
+        *v += 10;
+    }
+    assert_eq!(m.iter_mut().count(), 1);
+    assert_eq!(m.get(&"a"), Some(&11));
+    assert_eq!(m.peek(&"b"), Some(&2));
+    assert_eq!(m.len(), 2);
+}
+
+#[test]
+fn get_many_mut() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.enable_stats();
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(5));
+    m.insert("c", 3, Duration::from_secs(1));
+    let [a, b] = m.get_many_mut(["a", "b"]).unwrap();
+    std::mem::swap(a, b);
+    assert_eq!(m.get(&"a"), Some(&2));
+    assert!(m.get_many_mut(["a", "a"]).is_none());
+    assert!(m.get_many_mut(["a", "d"]).is_none());
+    clock.advance(Duration::from_secs(2));
+    assert!(m.get_many_mut(["a", "c"]).is_none());
+    let stats = m.stats().unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.expired), (5, 2, 1));
+    #[cfg(feature = "access-count")]
+    assert_eq!(m.peek_meta(&"a").unwrap().access_count(), 4);
+}
+
+#[test]
+fn take() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    assert_eq!(m.take(&"a"), Some(1));
+    assert_eq!(m.take(&"a"), None);
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.take(&"b"), None);
+    assert!(m.is_empty());
+}
+
+#[test]
+fn vacuum_partial() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    for i in 0..10 {
+        m.insert(i, i, Duration::from_secs(1));
+    }
+    m.insert(10, 10, Duration::from_secs(5));
+    clock.advance(Duration::from_secs(2));
+    let mut removed = 0;
+    for _ in 0..3 {
+        removed += m.vacuum_partial(4);
+    }
+    assert_eq!(removed, 10);
+    assert_eq!(m.len(), 1);
+    assert_eq!(m.vacuum_partial(4), 0);
+
+    // stale deadlines count against the budget, so each call stays bounded
+    for i in 0..10 {
+        m.insert(i, i, Duration::from_secs(1));
+        m.remove(&i);
+    }
+    m.insert(11, 11, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.vacuum_partial(4), 0);
+    assert_eq!(m.vacuum_partial(4), 0);
+    assert_eq!(m.vacuum_partial(4), 1);
+    assert_eq!(m.len(), 1);
+}
+
+#[test]
+fn remaining_and_ttl() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert_persistent("b", 2);
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.remaining(&"a"), Some(Duration::from_secs(3)));
+    assert_eq!(m.ttl(&"a"), Some(Duration::from_secs(5)));
+    assert_eq!(m.remaining(&"b"), Some(Duration::MAX));
+    assert_eq!(m.ttl(&"c"), None);
+    clock.advance(Duration::from_secs(3));
+    assert_eq!(m.remaining(&"a"), None);
+    assert_eq!(m.ttl(&"a"), None);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn reaper() {
+    use std::sync::{Arc, Mutex};
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let map = Arc::new(Mutex::new(ExpiringMap::new()));
+        map.lock()
+            .unwrap()
+            .insert("a", 1, Duration::from_millis(10));
+        let handle = crate::spawn_reaper(&map, Duration::from_millis(5));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(map.lock().unwrap().is_empty());
+        drop(map);
+        handle.await.unwrap();
+    });
+}
+
+#[test]
+fn sync_map() {
+    use crate::SyncExpiringMap;
+
+    let map = SyncExpiringMap::new();
+    std::thread::scope(|s| {
+        for i in 0..4 {
+            let map = &map;
+            s.spawn(move || map.insert(i, i * 2, Duration::from_secs(5)));
+        }
+    });
+    assert_eq!(map.get(&3), Some(6));
+    assert_eq!(map.remove(&3), Some(6));
+    assert_eq!(map.get(&3), None);
+    assert_eq!(map.vacuum(), 0);
+    assert_eq!(map.read().len(), 3);
+}
+
+#[test]
+fn set_insert() {
+    let clock = MockClock::new();
+    let mut s = ExpiringSet::with_clock(clock.clone());
+    assert!(s.insert("a", Duration::from_secs(1)));
+    assert!(!s.insert("a", Duration::from_secs(1)));
+    clock.advance(Duration::from_secs(2));
+    assert!(s.insert("a", Duration::from_secs(1)));
+}
+
+#[test]
+fn index() {
+    let mut m = ExpiringMap::new();
+    m.insert("a", 1, Duration::from_secs(5));
+    m[&"a"] += 1;
+    assert_eq!(m[&"a"], 2);
+}
+
+#[test]
+#[should_panic = "key is missing or expired"]
+fn index_expired() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    let _ = m[&"a"];
+}
+
+#[test]
+fn expired_count() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(1));
+    m.insert("c", 3, Duration::from_secs(5));
+    assert_eq!(m.expired_count(), 0);
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.expired_count(), 2);
+    assert!(m.contains_expired_key(&"a"));
+    assert!(!m.contains_expired_key(&"c"));
+    assert!(!m.contains_expired_key(&"d"));
+    m.vacuum();
+    assert_eq!(m.expired_count(), 0);
+}
+
+#[test]
+fn vacuum_tuning() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.set_minimum_vacuum_size(2);
+    m.set_vacuum_factor(2.0);
+    m.clear();
+    assert_eq!(m.last_size(), 2);
+    for i in 0..4 {
+        m.insert(i, i, Duration::from_secs(1));
+    }
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.vacuum_if_needed(), None);
+    m.insert(4, 4, Duration::from_secs(1));
+    assert_eq!(m.vacuum_if_needed(), Some(4));
+}
+
+#[test]
+#[should_panic = "vacuum factor must be greater than 1.0"]
+fn vacuum_factor_validated() {
+    ExpiringMap::<u8, u8>::new().set_vacuum_factor(1.0);
+}
+
+#[test]
+#[should_panic = "max len must be greater than 0"]
+fn max_len_validated() {
+    ExpiringMap::<u8, u8>::with_max_len(0);
+}
+
+#[test]
+fn keys_and_values() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.keys().collect::<Vec<_>>(), [&"a"]);
+    assert_eq!(m.values().collect::<Vec<_>>(), [&1]);
+}
+
+#[test]
+fn into_iter() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    let borrowed: Vec<_> = (&m).into_iter().collect();
+    assert_eq!(borrowed, [(&"a", &1)]);
+    let owned: Vec<_> = m.into_iter().collect();
+    assert_eq!(owned, [("a", 1)]);
+}
+
+#[test]
+fn merge() {
+    let clock = MockClock::new();
+    let mut a = ExpiringMap::with_clock(clock.clone());
+    let mut b = ExpiringMap::with_clock(clock.clone());
+    a.insert("short", 1, Duration::from_secs(2));
+    a.insert("long", 1, Duration::from_secs(8));
+    b.insert("short", 2, Duration::from_secs(6));
+    b.insert("long", 2, Duration::from_secs(4));
+    b.insert("new", 2, Duration::from_secs(4));
+    b.insert("dead", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(1));
+    a.merge(b);
+    assert_eq!(a.get(&"short"), Some(&2));
+    assert_eq!(a.get(&"long"), Some(&1));
+    assert_eq!(a.remaining(&"new"), Some(Duration::from_secs(3)));
+    assert!(a.peek(&"dead").is_none());
+}
+
+#[test]
+fn shrink_policy() {
+    use crate::ShrinkPolicy;
+
+    let clock = MockClock::new();
+    let capacity_after_vacuum = |policy| {
+        let mut m = ExpiringMap::with_clock(clock.clone());
+        m.set_shrink_policy(policy);
+        for i in 0..100 {
+            m.insert(i, i, Duration::from_secs(1));
+        }
+        m.insert(100, 100, Duration::from_secs(5));
+        clock.advance(Duration::from_secs(2));
+        m.vacuum();
+        m.capacity()
+    };
+    let never = capacity_after_vacuum(ShrinkPolicy::Never);
+    assert!(capacity_after_vacuum(ShrinkPolicy::Lazy) < never);
+    assert!(capacity_after_vacuum(ShrinkPolicy::Aggressive) < never);
+    assert!(capacity_after_vacuum(ShrinkPolicy::Below(25)) < never);
+}
+
+#[test]
+fn equality() {
+    let clock = MockClock::new();
+    let mut a = ExpiringMap::with_clock(clock.clone());
+    let mut b = ExpiringMap::with_clock(clock.clone());
+    a.insert("a", 1, Duration::from_secs(5));
+    a.insert("b", 2, Duration::from_secs(1));
+    b.insert("a", 1, Duration::from_secs(9));
+    assert_ne!(a, b);
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(a, b);
+    b.insert("a", 2, Duration::from_secs(9));
+    assert_ne!(a, b);
+
+    let mut x = ExpiringSet::with_clock(clock.clone());
+    let mut y = ExpiringSet::with_clock(clock);
+    x.insert(1, Duration::from_secs(1));
+    y.insert(1, Duration::from_secs(3));
+    assert_eq!(x, y);
+}
+
+#[test]
+fn get_purge() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.get_purge(&"a"), Some(&1));
+    assert_eq!(m.get_purge(&"b"), None);
+    assert_eq!(m.len(), 1);
+    assert_eq!(m.get_purge(&"c"), None);
+}
+
+#[test]
+fn insert_jittered() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock);
+    m.set_jitter_seed(7);
+    for i in 0..50 {
+        m.insert_jittered(i, i, Duration::from_secs(10), Duration::from_secs(2));
+    }
+    let ttls: Vec<_> = (0..50).map(|i| m.ttl(&i).unwrap()).collect();
+    assert!(ttls
+        .iter()
+        .all(|t| (Duration::from_secs(8)..=Duration::from_secs(12)).contains(t)));
+    assert!(ttls.iter().any(|t| *t != ttls[0]));
+
+    m.insert_jittered(100, 0, Duration::ZERO, Duration::from_secs(1));
+    assert!(m.ttl(&100).is_none_or(|t| t <= Duration::from_secs(1)));
+}
+
+#[test]
+fn entries_expiring_within() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("soon", 1, Duration::from_secs(3));
+    m.insert("later", 2, Duration::from_secs(10));
+    m.insert("gone", 3, Duration::from_secs(1));
+    m.insert_persistent("never", 4);
+    clock.advance(Duration::from_secs(2));
+    let soon: Vec<_> = m
+        .entries_expiring_within(Duration::from_secs(5))
+        .map(|(k, _)| *k)
+        .collect();
+    assert_eq!(soon, ["soon"]);
+}
+
+#[test]
+fn get_mut_meta() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(2));
+    let meta = m.get_mut_meta(&"a").unwrap();
+    **meta += 1;
+    meta.set_ttl(Duration::from_secs(4));
+    clock.advance(Duration::from_secs(3));
+    assert_eq!(m.get(&"a"), Some(&2));
+    clock.advance(Duration::from_secs(1));
+    assert!(m.get_mut_meta(&"a").is_none());
+}
+
+#[test]
+fn insert_if_absent() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    assert_eq!(m.insert_if_absent("a", 1, Duration::from_secs(2)), Ok(()));
+    assert_eq!(m.insert_if_absent("a", 2, Duration::from_secs(9)), Err(2));
+    assert_eq!(m.ttl(&"a"), Some(Duration::from_secs(2)));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.insert_if_absent("a", 3, Duration::from_secs(2)), Ok(()));
+    assert_eq!(m.get(&"a"), Some(&3));
+}
+
+#[test]
+fn max_weight() {
+    let mut m = ExpiringMap::with_max_weight(10);
+    m.insert("a", vec![0_u8; 4], Duration::from_secs(5));
+    m.insert("b", vec![0_u8; 4], Duration::from_secs(9));
+    assert_eq!(m.current_weight(), 8);
+    // a expires soonest, so it makes room
+    m.insert("c", vec![0_u8; 4], Duration::from_secs(7));
+    assert_eq!(m.current_weight(), 8);
+    assert!(m.get(&"a").is_none());
+    // replacing an entry only counts the difference
+    m.insert("c", vec![0_u8; 6], Duration::from_secs(7));
+    assert_eq!(m.current_weight(), 10);
+    assert_eq!(m.len(), 2);
+    // inserting through an entry makes room too, and c now expires soonest
+    m.entry("d", Duration::from_secs(1))
+        .or_insert_with(|| vec![0; 1]);
+    assert_eq!(m.current_weight(), 5);
+    assert!(m.get(&"c").is_none());
+    assert_eq!(m.take(&"b").map(|v| v.len()), Some(4));
+    m.vacuum();
+    assert_eq!(m.current_weight(), 1);
+    m.insert("e", vec![0_u8; 4], Duration::from_secs(5));
+    if let Entry::Occupied(mut d) = m.entry("d", Duration::from_secs(1)) {
+        d.insert(vec![0; 8]);
+    }
+    assert_eq!(m.current_weight(), 8);
+    assert!(m.get(&"e").is_none());
+    m.clear();
+    assert_eq!(m.current_weight(), 0);
+}
+
+#[test]
+fn stats() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.enable_stats();
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    m.get(&"a");
+    m.contains_key(&"a");
+    m.get_mut(&"b");
+    m.get(&"c");
+    let stats = m.stats().unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.expired), (2, 2, 1));
+    m.reset_stats();
+    assert_eq!(m.stats(), Some(crate::CacheStats::default()));
+
+    let mut m = ExpiringMap::with_max_len(1);
+    m.enable_stats();
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 1, Duration::from_secs(5));
+    assert_eq!(m.stats().unwrap().evictions, 1);
+
+    let m: ExpiringMap<&str, i32> = ExpiringMapBuilder::new().stats(true).build();
+    assert_eq!(m.stats(), Some(crate::CacheStats::default()));
+    let m = ExpiringMap::<&str, i32>::new();
+    m.get(&"a");
+    assert_eq!(m.stats(), None);
+}
+
+#[test]
+fn timer_wheel() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert_persistent(u64::MAX, 0);
+    m.enable_timer_wheel(Duration::from_millis(10));
+    for i in 1..=2000_u64 {
+        // multiples of the resolution up to about a day, across every level
+        let ttl = Duration::from_millis((i * i * 7919 % 8_640_000) * 10);
+        m.insert(i, i, ttl);
+    }
+    let steps = [10, 1270, 97_000, 3_600_000].map(Duration::from_millis);
+    let mut removed = 0;
+    for step in steps.iter().cycle().take(200) {
+        clock.advance(*step);
+        removed += m.tick();
+        assert_eq!(m.expired_count(), 0);
+    }
+    assert_eq!(removed, 2000);
+    assert_eq!(m.len(), 1);
+}
+
+#[test]
+fn key_value_meta() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert(String::from("a"), 1, Duration::from_secs(1));
+    let (k, v) = m.get_key_value_meta("a").unwrap();
+    assert_eq!((k.as_str(), **v), ("a", 1));
+    assert_eq!(v.ttl(), Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    assert!(m.get_key_value_meta("a").is_none());
+    let (k, v) = m.peek_key_value("a").unwrap();
+    assert_eq!((k.as_str(), **v), ("a", 1));
+}
+
+#[test]
+fn insert_batch() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert_batch((0..20).map(|i| (i, i * 2)), Duration::from_secs(1));
+    assert_eq!(m.len_live(), 20);
+    assert_eq!(m.get(&7), Some(&14));
+    assert_eq!(m.expires_at(&0), m.expires_at(&19));
+    clock.advance(Duration::from_secs(1));
+    assert!(m.is_empty_live());
+}
+
+#[test]
+fn drain() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert(1, "one", Duration::from_secs(5));
+    m.insert(2, "two", Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    let drained: Vec<_> = m.drain().collect();
+    assert_eq!(drained, vec![(1, "one")]);
+    assert!(m.is_empty());
+    assert_eq!(m.last_size(), m.minimum_vacuum_size());
+    m.insert(3, "three", Duration::from_secs(1));
+    assert_eq!(m.get(&3), Some(&"three"));
+}
+
+#[test]
+fn soonest_and_latest_expiring() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    assert!(m.soonest_expiring().is_none());
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(5));
+    m.insert("c", 3, Duration::from_secs(3));
+    assert_eq!(m.soonest_expiring().map(|(k, _)| *k), Some("a"));
+    assert_eq!(m.latest_expiring().map(|(k, v)| (*k, **v)), Some(("b", 2)));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.soonest_expiring().map(|(k, _)| *k), Some("c"));
+    m.insert_persistent("d", 4);
+    assert_eq!(m.latest_expiring().map(|(k, _)| *k), Some("d"));
+}
+
+#[test]
+fn builder() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMapBuilder::new()
+        .default_ttl(Duration::from_secs(1))
+        .capacity(32)
+        .vacuum_factor(2.0)
+        .min_vacuum_size(4)
+        .max_len(2)
+        .shrink_after_vacuum(25)
+        .clock(clock.clone())
+        .build();
+    assert!(m.capacity() >= 32);
+    assert!((m.vacuum_factor() - 2.0).abs() < f32::EPSILON);
+    assert_eq!(m.minimum_vacuum_size(), 4);
+    assert_eq!(m.shrink_policy(), crate::ShrinkPolicy::Below(25));
+    m.insert_default(1, 'a');
+    m.insert_default(2, 'b');
+    m.insert_default(3, 'c');
+    assert_eq!(m.len_live(), 2);
+    clock.advance(Duration::from_secs(1));
+    assert!(m.is_empty_live());
+}
+
+#[test]
+fn try_insert() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    *m.try_insert("a", 1, Duration::from_secs(1)).unwrap() += 10;
+    let err = m.try_insert("a", 2, Duration::from_secs(1)).unwrap_err();
+    assert_eq!((*err.existing, err.value), (11, 2));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        m.try_insert("a", 3, Duration::from_secs(1)).ok(),
+        Some(&mut 3)
+    );
+    assert_eq!(m.get(&"a"), Some(&3));
+}
+
+#[test]
+fn reset_all_ttls() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.enable_expiry_index();
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    m.reset_all_ttls(Duration::from_secs(1));
+    assert_eq!(m.remaining(&"a"), Some(Duration::from_secs(1)));
+    assert_eq!(m.get(&"b"), None);
+    assert!(m.contains_expired_key(&"b"));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.vacuum_due(), 2);
+    assert!(m.is_empty());
+}
+
+#[test]
+fn get_or_insert_with_ttl() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    let v = m.get_or_insert_with_ttl("a", || (1, Duration::from_secs(3)));
+    assert_eq!(*v, 1);
+    assert_eq!(m.remaining(&"a"), Some(Duration::from_secs(3)));
+    let v = m.get_or_insert_with_ttl("a", || unreachable!("the value is live"));
+    assert_eq!(*v, 1);
+    clock.advance(Duration::from_secs(3));
+    let v = m.get_or_insert_with_ttl("a", || (2, Duration::from_secs(1)));
+    assert_eq!(*v, 2);
+}
+
+#[test]
+fn map_values() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", "1", Duration::from_secs(3));
+    m.insert("b", "2", Duration::from_secs(1));
+    m.insert_persistent("c", "3");
+    clock.advance(Duration::from_secs(1));
+    let parsed = m.map_values(|v| v.parse::<u32>().unwrap());
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed.get(&"a"), Some(&1));
+    assert_eq!(parsed.remaining(&"a"), Some(Duration::from_secs(2)));
+    assert!(parsed
+        .get_meta(&"c")
+        .is_some_and(crate::ExpiryValue::is_persistent));
+}
+
+#[test]
+fn contains_key_uses_map_clock() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.enable_stats();
+    m.insert("a", (), Duration::from_secs(1));
+    assert!(m.contains_key(&"a"));
+    clock.advance(Duration::from_secs(1));
+    assert!(!m.contains_key(&"a"));
+    let stats = m.stats().unwrap();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+}
+
+#[test]
+fn entry_or_default() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    for _ in 0..3 {
+        *m.entry("events", Duration::from_secs(1)).or_default() += 1;
+    }
+    assert_eq!(m.get(&"events"), Some(&3));
+    clock.advance(Duration::from_secs(1));
+    let count = m
+        .entry("events", Duration::from_secs(1))
+        .and_modify(|c| *c += 1)
+        .or_default();
+    assert_eq!(*count, 0);
+}
+
+#[test]
+fn remaining_opt() {
+    let mut m = ExpiringMap::new();
+    m.insert("a", (), Duration::from_secs(30));
+    m.insert_persistent("b", ());
+    let a = m.get_meta(&"a").unwrap();
+    assert!(a
+        .remaining_opt()
+        .is_some_and(|r| r <= Duration::from_secs(30)));
+    let b = m.get_meta(&"b").unwrap();
+    assert_eq!(b.remaining_opt(), None);
+    assert_eq!(b.remaining(), Duration::MAX);
+    assert!(b.not_expired());
+}
+
+#[test]
+fn replace_value() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(3));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.replace_value(&"a", 2), Some(1));
+    assert_eq!(m.get(&"a"), Some(&2));
+    assert_eq!(m.remaining(&"a"), Some(Duration::from_secs(2)));
+    assert_eq!(m.replace_value(&"b", 3), None);
+    assert!(!m.contains_key(&"b"));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.replace_value(&"a", 4), None);
+}
+
+#[test]
+fn replace_value_makes_weight_room() {
+    let mut m = ExpiringMap::with_max_weight(10);
+    m.insert("a", vec![0_u8; 4], Duration::from_secs(9));
+    m.insert("b", vec![0_u8; 4], Duration::from_secs(5));
+    assert_eq!(m.replace_value(&"a", vec![0; 8]).map(|v| v.len()), Some(4));
+    assert_eq!(m.current_weight(), 8);
+    assert!(m.get(&"b").is_none());
+}
+
+#[test]
+fn count_where() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("alice", true, Duration::from_secs(5));
+    m.insert("bob", false, Duration::from_secs(5));
+    m.insert("carol", true, Duration::from_secs(1));
+    assert_eq!(m.count_where(|_, admin| *admin), 2);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.count_where(|_, admin| *admin), 1);
+    assert_eq!(m.count_where(|k, _| k.starts_with('b')), 1);
+}
+
+#[test]
+fn refresh_or_insert() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    assert_eq!(
+        m.refresh_or_insert(String::from("a"), 1, Duration::from_secs(2)),
+        None
+    );
+    clock.advance(Duration::from_secs(1));
+    let old = m.refresh_or_insert(String::from("a"), 2, Duration::from_secs(2));
+    assert_eq!(old, Some(1));
+    assert_eq!(m.remaining("a"), Some(Duration::from_secs(2)));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(
+        m.refresh_or_insert(String::from("a"), 3, Duration::from_secs(2)),
+        None
+    );
+    assert_eq!(m.get("a"), Some(&3));
+}
+
+#[test]
+fn hashmap_conversions() {
+    let plain: std::collections::HashMap<_, _> = [("a", 1), ("b", 2)].into_iter().collect();
+    let mut m: ExpiringMap<_, _, _, MockClock> =
+        ExpiringMap::from_hashmap_with_ttl(plain, Duration::from_secs(2));
+    assert_eq!(m.get(&"a"), Some(&1));
+    assert_eq!(m.remaining(&"b"), Some(Duration::from_secs(2)));
+    m.insert("c", 3, Duration::from_secs(1));
+    m.clock().advance(Duration::from_secs(1));
+    let plain = m.into_hashmap();
+    assert_eq!(plain.len(), 2);
+    assert_eq!(plain.get("b"), Some(&2));
+}
+
+#[test]
+fn expire_now() {
+    let mut m = ExpiringMap::new();
+    m.insert("a", 1, Duration::from_secs(30));
+    assert!(m.expire_now(&"a"));
+    assert_eq!(m.get(&"a"), None);
+    assert_eq!(m.peek(&"a"), Some(&1));
+    assert!(!m.expire_now(&"a"));
+    assert!(!m.expire_now(&"b"));
+    assert_eq!(m.vacuum(), 1);
+    assert_eq!(m.peek(&"a"), None);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn get_or_insert_with_async() {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = pin!(f);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(out) = f.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    let v = block_on(m.get_or_insert_with_async("a", Duration::from_secs(1), async { 1 }));
+    assert_eq!(*v, 1);
+    let v = block_on(
+        m.get_or_insert_with_async("a", Duration::from_secs(1), async {
+            unreachable!("the value is live")
+        }),
+    );
+    assert_eq!(*v, 1);
+    clock.advance(Duration::from_secs(1));
+    let v = block_on(m.get_or_insert_with_async("a", Duration::from_secs(1), async { 2 }));
+    assert_eq!(*v, 2);
+}
+
+#[test]
+fn estimated_memory() {
+    let mut m: ExpiringMap<u64, u64> = ExpiringMap::new();
+    let empty = m.estimated_memory();
+    m.reserve(100);
+    assert!(m.estimated_memory() >= empty + 100 * 2 * size_of::<u64>());
+}
+
+#[test]
+fn vacuum_older_than() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("old", 1, Duration::from_secs(50));
+    m.insert("expired", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(2));
+    let deploy = clock.now();
+    m.insert("new", 3, Duration::from_secs(50));
+    assert_eq!(m.vacuum_older_than(deploy), 2);
+    assert_eq!(m.len(), 1);
+    assert_eq!(m.get(&"new"), Some(&3));
+}
+
+#[test]
+fn debug_summary() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(1));
+    let summary = format!("{m:?}");
+    assert!(summary.starts_with("ExpiringMap { live: 1, expired_pending: 1, capacity: "));
+    assert!(!summary.contains("entries"));
+    assert!(format!("{m:#?}").contains("entries"));
+}
+
+#[test]
+fn get_all() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.enable_stats();
+    m.insert("a", 1, Duration::from_secs(5));
+    m.insert("b", 2, Duration::from_secs(1));
+    m.insert("c", 3, Duration::from_secs(5));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        m.get_all(["c", "b", "x", "a"].iter()),
+        vec![Some(&3), None, None, Some(&1)]
+    );
+    assert_eq!(m.stats().unwrap().hits, 2);
+}
+
+#[test]
+fn max_ttl_never_overflows() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.enable_expiry_index();
+    m.insert("max", 1, Duration::MAX);
+    m.insert("soon", 2, Duration::from_secs(1));
+    assert_eq!(m.expires_at(&"max"), None);
+    assert_eq!(m.next_expiry(), m.expires_at(&"soon"));
+    m.extend_ttl(&"max", Duration::MAX);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.next_expiry(), None);
+    assert_eq!(m.time_until_next_expiry(), None);
+    assert_eq!(m.vacuum_due(), 1);
+    assert_eq!(
+        m.remaining(&"max"),
+        Duration::MAX.checked_sub(Duration::from_secs(1))
+    );
+    assert_eq!(m.get(&"max"), Some(&1));
+
+    let mut wheel = ExpiringMap::with_clock(clock);
+    wheel.enable_timer_wheel(Duration::from_millis(10));
+    wheel.insert("max", 1, Duration::MAX);
+    assert_eq!(wheel.tick(), 0);
+    assert!(wheel.contains_key(&"max"));
+}
+
+#[test]
+#[cfg(feature = "access-count")]
+fn most_accessed_ranks_reads() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(10));
+    m.insert("c", 3, Duration::from_secs(10));
+    for _ in 0..5 {
+        m.get(&"a");
+    }
+    m.get(&"b");
+    *m.get_mut(&"b").unwrap() += 1;
+    m.peek(&"c");
+    assert_eq!(m.peek_meta(&"b").unwrap().access_count(), 2);
+    assert_eq!(m.peek_meta(&"c").unwrap().access_count(), 0);
+    assert_eq!(m.most_accessed(2), vec![(&"a", 5), (&"b", 2)]);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.most_accessed(5), vec![(&"b", 2), (&"c", 0)]);
+}
+
+#[test]
+fn set_iter_skips_expired() {
+    let clock = MockClock::new();
+    let mut s = ExpiringSet::with_clock(clock.clone());
+    s.insert("online", Duration::from_secs(10));
+    s.insert("away", Duration::from_secs(1));
+    let mut members: Vec<_> = s.iter().copied().collect();
+    members.sort_unstable();
+    assert_eq!(members, ["away", "online"]);
+    assert_eq!(s.len_live(), 2);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(s.iter().collect::<Vec<_>>(), [&"online"]);
+    assert_eq!(s.len_live(), 1);
+    assert_eq!(s.len(), 2);
+}
+
+#[test]
+fn vacuum_on_read_cleans_up() {
+    let clock = MockClock::new();
+    let build = |on_read| {
+        let mut m = ExpiringMapBuilder::new()
+            .clock(clock.clone())
+            .min_vacuum_size(4)
+            .vacuum_on_read(on_read)
+            .build();
+        for i in 0..7 {
+            m.insert(i, i, Duration::from_secs(1));
+        }
+        m
+    };
+    let mut off = build(false);
+    let mut on = build(true);
+    assert!(on.vacuum_on_read());
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(off.get_mut(&0), None);
+    assert_eq!(on.get_mut(&0), None);
+    assert_eq!(off.len(), 7);
+    assert_eq!(on.len(), 0);
+
+    // shared reads can't vacuum, so they leave it to the next mutable call
+    let off = build(false);
+    let mut on = build(true);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(on.get(&0), None);
+    assert_eq!(on.len(), 7);
+    assert_eq!(on.vacuum_if_needed(), Some(7));
+    assert_eq!(off.get(&0), None);
+    assert!(!off.vacuum_pending());
+
+    let sync = crate::SyncExpiringMap::from(build(true));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(sync.get(&0), None);
+    assert!(sync.read().is_empty());
+}
+
+#[test]
+fn remove_all_counts_live() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(10));
+    m.insert("c", 3, Duration::from_secs(10));
+    m.insert("d", 4, Duration::from_secs(10));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.remove_all(["a", "b", "c", "missing"].iter()), 2);
+    assert_eq!(m.len(), 1);
+    assert_eq!(m.get(&"d"), Some(&4));
+}
+
+#[test]
+fn custom_vacuum_triggers() {
+    struct Always;
+    impl VacuumTrigger for Always {
+        fn should_vacuum(&self, _: usize, _: usize) -> bool {
+            true
+        }
+    }
+    assert!(GrowthTrigger::default().should_vacuum(8, 13));
+    assert!(!GrowthTrigger::new(2.0).should_vacuum(8, 13));
+
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.set_vacuum_trigger(Always);
+    m.insert("a", 1, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.vacuum_if_needed(), Some(1));
+
+    let mut m = ExpiringMapBuilder::new()
+        .clock(clock.clone())
+        .vacuum_trigger(TimeTrigger::with_clock(
+            Duration::from_secs(10),
+            clock.clone(),
+        ))
+        .build();
+    m.insert("a", 1, Duration::from_secs(1));
+    assert_eq!(m.vacuum_if_needed(), None);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.vacuum_if_needed(), None);
+    clock.advance(Duration::from_secs(9));
+    assert_eq!(m.vacuum_if_needed(), Some(1));
+}
+
+#[test]
+fn get_or_try_insert_with_skips_errors() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    let failed: Result<&mut i32, &str> =
+        m.get_or_try_insert_with("a", Duration::from_secs(1), || Err("db down"));
+    assert_eq!(failed, Err("db down"));
+    assert!(m.is_empty());
+    let ok = m.get_or_try_insert_with("a", Duration::from_secs(1), || Ok::<_, &str>(1));
+    assert_eq!(ok, Ok(&mut 1));
+    let hit = m.get_or_try_insert_with("a", Duration::from_secs(1), || -> Result<i32, &str> {
+        panic!("called on a hit")
+    });
+    assert_eq!(hit, Ok(&mut 1));
+    clock.advance(Duration::from_secs(1));
+    let refetched = m.get_or_try_insert_with("a", Duration::from_secs(1), || Ok::<_, &str>(2));
+    assert_eq!(refetched, Ok(&mut 2));
+
+    // a full map keeps all of its entries when `f` fails
+    let mut full = ExpiringMap::with_max_len(2);
+    full.insert("a", 1, Duration::from_secs(30));
+    full.insert("b", 2, Duration::from_secs(30));
+    let failed: Result<&mut i32, &str> =
+        full.get_or_try_insert_with("c", Duration::from_secs(30), || Err("db down"));
+    assert_eq!(failed, Err("db down"));
+    assert_eq!(full.len(), 2);
+    assert_eq!(full.get(&"a"), Some(&1));
+    assert_eq!(full.get(&"b"), Some(&2));
+    full.get_or_insert("c", 3, Duration::from_secs(30));
+    assert_eq!(full.len(), 2);
+    assert_eq!(full.get(&"c"), Some(&3));
+}
+
+#[test]
+fn frozen_map_keeps_deadlines() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(10));
+    m.insert("gone", 2, Duration::from_secs(1));
+    clock.advance(Duration::from_secs(4));
+    m.set_frozen(true);
+    assert!(m.is_frozen());
+    m.insert("b", 3, Duration::from_secs(5));
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(m.get(&"a"), Some(&1));
+    assert_eq!(m.get(&"gone"), None);
+    assert_eq!(m.vacuum(), 0);
+    assert_eq!(m.remaining(&"a"), Some(Duration::from_secs(6)));
+
+    m.set_frozen(false);
+    assert_eq!(m.remaining(&"a"), Some(Duration::from_secs(6)));
+    assert_eq!(m.remaining(&"b"), Some(Duration::from_secs(5)));
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(m.get(&"b"), None);
+    assert_eq!(m.get(&"a"), Some(&1));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.get(&"a"), None);
+    assert_eq!(m.vacuum(), 3);
+}
+
+#[test]
+fn retain_meta_sees_ttls() {
+    let mut m = ExpiringMap::new();
+    m.insert("short", 1, Duration::from_secs(5));
+    m.insert("long", 2, Duration::from_secs(50));
+    m.insert("forever", 3, Duration::from_secs(500));
+    m.retain_meta(|_, v| v.remaining() > Duration::from_secs(10) && **v != 3);
+    assert_eq!(m.len(), 1);
+    assert_eq!(m.get(&"long"), Some(&2));
+}
+
+#[test]
+fn expiry_sink_receives_vacuumed() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    let (tx, rx) = std::sync::mpsc::channel();
+    m.set_expiry_sink(tx);
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(10));
+    clock.advance(Duration::from_secs(1));
+    let worker = std::thread::spawn(move || rx.iter().collect::<Vec<_>>());
+    assert_eq!(m.vacuum(), 1);
+    drop(m);
+    assert_eq!(worker.join().unwrap(), [("a", 1)]);
+}
+
+#[test]
+#[cfg(not(feature = "wasm"))]
+fn coarse_clock_lags_by_resolution() {
+    let mut exact = ExpiringMap::with_clock_resolution(Duration::ZERO);
+    exact.insert("v", 1, Duration::from_millis(20));
+    sleep(Duration::from_millis(30));
+    assert_eq!(exact.get(&"v"), None);
+
+    let clock = CoarseClock::new(Duration::from_secs(30));
+    let before = clock.now();
+    sleep(Duration::from_millis(20));
+    assert_eq!(clock.now(), before);
+
+    let coarse = CoarseClock::new(Duration::from_millis(5));
+    let before = coarse.now();
+    sleep(Duration::from_millis(50));
+    assert!(coarse.now() > before);
+}
+
+/// a [`Clock`] which counts how many times it has been read
+#[cfg(not(feature = "wasm"))]
+#[derive(Clone, Default)]
+struct CountingClock(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+#[cfg(not(feature = "wasm"))]
+impl Clock for CountingClock {
+    fn now(&self) -> std::time::Instant {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        std::time::Instant::now()
+    }
+}
+
+#[test]
+#[cfg(not(feature = "wasm"))]
+fn coarse_clock_avoids_reads() {
+    let source = CountingClock::default();
+    let clock = CoarseClock::ticking(Duration::from_secs(30), source.clone());
+    let mut m = ExpiringMap::with_clock(clock);
+    for i in 0..1000 {
+        m.insert(i, i, Duration::from_secs(5));
+        assert_eq!(m.get(&i), Some(&i));
+    }
+    // the only reading is the one taken when the clock was created
+    assert_eq!(source.0.load(std::sync::atomic::Ordering::Relaxed), 1);
+}
+
+#[test]
+fn set_default_ttl() {
+    let clock = MockClock::new();
+    let mut s = ExpiringSet::with_clock(clock.clone());
+    s.set_default_ttl(Duration::from_millis(50));
+    assert!(s.insert_default("a"));
+    assert!(!s.insert_default("a"));
+    s.insert("b", Duration::from_secs(10));
+    assert_eq!(s.default_ttl(), Duration::from_millis(50));
+    clock.advance(Duration::from_millis(75));
+    assert!(!s.contains(&"a"));
+    assert!(s.contains(&"b"));
+}
+
+/// a clock which moves forward a second every time it is read
+#[derive(Clone, Default)]
+struct TickingClock(std::sync::Arc<std::sync::atomic::AtomicU64>);
+
+impl Clock for TickingClock {
+    fn now(&self) -> std::time::Instant {
+        static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+        let secs = self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        *START.get_or_init(std::time::Instant::now) + Duration::from_secs(secs)
+    }
+}
+
+#[test]
+fn batch_operations_read_the_clock_once() {
+    let clock = TickingClock::default();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(3));
+    assert!(!m.peek_meta(&"a").unwrap().expired_at(clock.now()));
+    // judged at 2s, where reading the clock again would be at 3s
+    let (key, _) = m.soonest_expiring().unwrap();
+    assert_eq!(key, &"a");
+    assert!(m.peek_meta(&"a").unwrap().expired_at(clock.now()));
+}
+
+#[test]
+fn pop_expired_one_at_a_time() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(1));
+    m.insert("c", 3, Duration::from_secs(10));
+    assert_eq!(m.pop_expired_one(), None);
+    clock.advance(Duration::from_secs(1));
+    let mut popped = vec![m.pop_expired_one().unwrap(), m.pop_expired_one().unwrap()];
+    popped.sort_unstable();
+    assert_eq!(popped, [("a", 1), ("b", 2)]);
+    assert_eq!(m.pop_expired_one(), None);
+    assert_eq!(m.len(), 1);
+}
+
+#[test]
+fn pop_expired_one_uses_index() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.enable_expiry_index();
+    m.set_minimum_vacuum_size(1);
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(2));
+    m.insert("c", 3, Duration::from_secs(10));
+    m.remove(&"a");
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(m.pop_expired_one(), Some(("b", 2)));
+    assert_eq!(m.last_size(), 1);
+    assert_eq!(m.pop_expired_one(), None);
+}
+
+#[test]
+fn get_or_insert_eager() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    *m.get_or_insert("a", 1, Duration::from_secs(1)) += 10;
+    assert_eq!(m.get_or_insert("a", 5, Duration::from_secs(1)), &mut 11);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.get_or_insert("a", 5, Duration::from_secs(1)), &mut 5);
+}
+
+#[test]
+fn ttl_policy_picks_ttls() {
+    let mut m: ExpiringMap<&str, Result<u8, u8>, _, _> = ExpiringMap::with_clock(MockClock::new());
+    m.set_default_ttl(Duration::from_secs(3));
+    m.insert_policied("default", Ok(0));
+    m.set_ttl_policy(|_, v: &Result<u8, u8>| {
+        if v.is_ok() {
+            Duration::from_secs(200)
+        } else {
+            Duration::from_secs(5)
+        }
+    });
+    m.insert_policied("ok", Ok(1));
+    m.insert_policied("err", Err(2));
+    m.insert("explicit", Err(3), Duration::from_secs(50));
+    let copy = m.clone();
+    assert_eq!(copy.ttl(&"ok"), Some(Duration::from_secs(200)));
+    assert_eq!(m.ttl(&"err"), Some(Duration::from_secs(5)));
+    assert_eq!(m.ttl(&"default"), Some(Duration::from_secs(3)));
+    assert_eq!(m.ttl(&"explicit"), Some(Duration::from_secs(50)));
+}
+
+#[test]
+fn fraction_remaining_bounds() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_millis(100));
+    m.insert("zero", 2, Duration::ZERO);
+    m.insert_persistent("forever", 3);
+    clock.advance(Duration::from_millis(50));
+    let now = clock.now();
+    let a = m.peek_meta(&"a").unwrap();
+    assert_eq!(a.elapsed_at(now), Duration::from_millis(50));
+    assert!((a.fraction_remaining_at(now) - 0.5).abs() < f64::EPSILON);
+    let zero = m.peek_meta(&"zero").unwrap();
+    assert!((zero.fraction_remaining_at(now) - 1.0).abs() < f64::EPSILON);
+    let forever = m.peek_meta(&"forever").unwrap();
+    assert!((forever.fraction_remaining_at(now) - 1.0).abs() < f64::EPSILON);
+    clock.advance(Duration::from_millis(75));
+    let a = m.peek_meta(&"a").unwrap();
+    assert!(a.fraction_remaining_at(clock.now()).abs() < f64::EPSILON);
+}
+
+/// a hasher which counts how many keys it has hashed
+#[derive(Clone, Default)]
+struct CountingState(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+impl std::hash::BuildHasher for CountingState {
+    type Hasher = std::collections::hash_map::DefaultHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Self::Hasher::default()
+    }
+}
+
+#[test]
+fn entry_paths_hash_once() {
+    let state = CountingState::default();
+    let hashes = || state.0.swap(0, std::sync::atomic::Ordering::Relaxed);
+    let mut m = ExpiringMap::with_capacity_hasher_and_clock(16, state.clone(), MockClock::new());
+    let key = "k".repeat(4096);
+    m.get_or_insert_with(key.clone(), Duration::from_secs(1), || 1);
+    assert_eq!(hashes(), 1);
+    m.get_or_insert_with(key.clone(), Duration::from_secs(1), || 2);
+    assert_eq!(hashes(), 1);
+    m.get_or_insert(key.clone(), 3, Duration::from_secs(1));
+    assert_eq!(hashes(), 1);
+    m.entry(key, Duration::from_secs(1)).or_insert(4);
+    assert_eq!(hashes(), 1);
+}
+
+#[test]
+fn split_expired_keeps_deadlines() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("old", 1, Duration::from_secs(1));
+    m.insert("new", 2, Duration::from_secs(10));
+    clock.advance(Duration::from_secs(4));
+    let (live, expired) = m.split_expired();
+    assert_eq!(expired, [("old", 1)]);
+    assert_eq!(live.len(), 1);
+    assert_eq!(live.remaining(&"new"), Some(Duration::from_secs(6)));
+}
+
+#[test]
+fn set_contains_batches() {
+    let clock = MockClock::new();
+    let mut s = ExpiringSet::with_clock(clock.clone());
+    s.insert("a", Duration::from_secs(10));
+    s.insert("b", Duration::from_secs(1));
+    assert!(s.contains_all(["a", "b"].iter()));
+    assert!(s.contains_all(std::iter::empty::<&&str>()));
+    assert!(!s.contains_any(std::iter::empty::<&&str>()));
+    clock.advance(Duration::from_secs(1));
+    assert!(!s.contains_all(["a", "b"].iter()));
+    assert!(s.contains_any(["b", "a"].iter()));
+    assert!(!s.contains_any(["b", "c"].iter()));
+}
+
+#[test]
+fn reserve_live_skips_expired() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    for i in 0..100 {
+        m.insert(i, i, Duration::from_secs(1));
+    }
+    m.insert(100, 100, Duration::from_secs(10));
+    clock.advance(Duration::from_secs(1));
+    m.reserve_live(10);
+    assert_eq!(m.len(), 1);
+    assert!(m.capacity() >= 11);
+}
+
+#[test]
+fn compact_rebuilds_tightly() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    for i in 0..1000 {
+        m.insert(i, i, Duration::from_secs(1));
+    }
+    m.insert(1000, 1000, Duration::from_secs(10));
+    clock.advance(Duration::from_secs(1));
+    let before = m.capacity();
+    m.compact();
+    assert_eq!(m.len(), 1);
+    assert!(m.capacity() < before);
+    assert_eq!(m.get(&1000), Some(&1000));
+}
+
+#[test]
+fn ttl_histogram_buckets() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("gone", 0, Duration::from_secs(1));
+    m.insert("a", 1, Duration::from_secs(2));
+    m.insert("b", 2, Duration::from_secs(6));
+    m.insert("c", 3, Duration::from_secs(11));
+    m.insert("d", 4, Duration::from_secs(20));
+    m.insert_persistent("e", 5);
+    clock.advance(Duration::from_secs(1));
+    let buckets = [
+        Duration::from_secs(1),
+        Duration::from_secs(5),
+        Duration::from_secs(10),
+    ];
+    assert_eq!(m.ttl_histogram(&buckets), [1, 1, 1, 2]);
+    assert_eq!(m.ttl_histogram(&[]), [5]);
+}
+
+#[test]
+fn set_take_if_checks_predicate() {
+    let clock = MockClock::new();
+    let mut s = ExpiringSet::with_clock(clock.clone());
+    s.insert(String::from("token-a"), Duration::from_secs(1));
+    s.insert(String::from("other"), Duration::from_secs(1));
+    assert_eq!(s.take_if("other", |k| k.starts_with("token")), None);
+    assert!(s.contains("other"));
+    assert_eq!(
+        s.take_if("token-a", |k| k.starts_with("token")),
+        Some(String::from("token-a"))
+    );
+    assert!(!s.contains("token-a"));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(s.take_if("other", |_| true), None);
+}
+
+#[test]
+fn update_mutates_and_renews() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("lease", 1, Duration::from_secs(2));
+    clock.advance(Duration::from_secs(1));
+    assert!(m.update(&"lease", Duration::from_secs(5), |v| *v += 1));
+    assert_eq!(m.get(&"lease"), Some(&2));
+    assert_eq!(m.remaining(&"lease"), Some(Duration::from_secs(5)));
+    assert!(!m.update(&"missing", Duration::from_secs(5), |_| panic!("missing")));
+    clock.advance(Duration::from_secs(5));
+    assert!(!m.update(&"lease", Duration::from_secs(5), |_| panic!("expired")));
+}
+
+#[test]
+fn into_keys_and_values_skip_expired() {
+    let clock = MockClock::new();
+    let mut m = ExpiringMap::with_clock(clock.clone());
+    m.insert("a", 1, Duration::from_secs(1));
+    m.insert("b", 2, Duration::from_secs(10));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(m.clone().into_keys().collect::<Vec<_>>(), ["b"]);
+    assert_eq!(m.into_values().collect::<Vec<_>>(), [2]);
+}
+
+#[test]
+fn entry_evictions_reach_listener() {
+    use std::sync::{Arc, Mutex};
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let mut m = ExpiringMap::with_max_len(2);
+    let log = Arc::clone(&evicted);
+    m.on_evict(move |k, v| log.lock().unwrap().push((*k, v)));
+    m.entry("a", Duration::from_secs(5)).or_insert(1);
+    m.entry("b", Duration::from_secs(5)).or_insert(2);
+    m.entry("c", Duration::from_secs(5)).or_insert(3);
+    assert_eq!(m.len(), 2);
+    assert_eq!(*evicted.lock().unwrap(), [("a", 1)]);
+}
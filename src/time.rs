@@ -0,0 +1,68 @@
+//! The [`Instant`] type used throughout the crate. With the `wasm` feature
+//! this comes from `web_time`, since [`std::time::Instant`] panics on
+//! `wasm32-unknown-unknown`. Without the `std` feature, it is a plain offset
+//! from a starting point chosen by the [`Clock`](crate::Clock).
+
+#[cfg(all(feature = "std", not(feature = "wasm")))]
+pub use std::time::Instant;
+
+#[cfg(feature = "wasm")]
+pub use web_time::Instant;
+
+#[cfg(not(feature = "std"))]
+pub use self::no_std::Instant;
+
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use core::{ops::Add, time::Duration};
+
+    /// A point in time, measured as how long it is after a starting point
+    /// chosen by a [`Clock`](crate::Clock), such as when a device booted. Only
+    /// instants from the same clock can be compared.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Instant(Duration);
+
+    impl Instant {
+        /// The instant `since_start` after the clock's starting point
+        pub const fn from_duration(since_start: Duration) -> Self {
+            Self(since_start)
+        }
+
+        /// How long this instant is after the clock's starting point
+        pub const fn as_duration(&self) -> Duration {
+            self.0
+        }
+
+        /// How long after `earlier` this instant is, or zero if `earlier` is
+        /// later
+        pub const fn duration_since(&self, earlier: Self) -> Duration {
+            self.saturating_duration_since(earlier)
+        }
+
+        /// How long after `earlier` this instant is, or zero if `earlier` is
+        /// later
+        pub const fn saturating_duration_since(&self, earlier: Self) -> Duration {
+            self.0.saturating_sub(earlier.0)
+        }
+
+        /// The instant `duration` after this one, or [`None`] if it can't be
+        /// represented
+        pub const fn checked_add(&self, duration: Duration) -> Option<Self> {
+            match self.0.checked_add(duration) {
+                Some(since_start) => Some(Self(since_start)),
+                None => None,
+            }
+        }
+    }
+
+    impl Add<Duration> for Instant {
+        type Output = Self;
+
+        /// # Panics
+        /// If the result can't be represented
+        fn add(self, rhs: Duration) -> Self {
+            self.checked_add(rhs)
+                .expect("overflow when adding duration to instant")
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use alloc::sync::Arc;
+use core::{
+    fmt::{self, Debug, Formatter},
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use crate::{atomic::AtomicU64, time::Instant, Clock, SystemClock};
+
+/// Decides when [`ExpiringMap::vacuum_if_needed`](crate::ExpiringMap::vacuum_if_needed)
+/// vacuums. Set one with
+/// [`ExpiringMap::set_vacuum_trigger`](crate::ExpiringMap::set_vacuum_trigger).
+pub trait VacuumTrigger {
+    /// Whether to vacuum now. `last_size` is how many entries the map had
+    /// after its last vacuum, and `current_size` is how many it has now,
+    /// expired or not.
+    fn should_vacuum(&self, last_size: usize, current_size: usize) -> bool;
+}
+
+/// Vacuum once the map has grown by more than `factor` times its size after
+/// the last vacuum. This is the default, with the map's
+/// [vacuum factor](crate::ExpiringMap::vacuum_factor).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrowthTrigger {
+    factor: f32,
+}
+
+impl GrowthTrigger {
+    /// Create a trigger which vacuums after the map grows by `factor`
+    ///
+    /// # Panics
+    /// If `factor` is not greater than 1.0
+    pub fn new(factor: f32) -> Self {
+        assert!(factor > 1.0, "vacuum factor must be greater than 1.0");
+        Self { factor }
+    }
+}
+
+impl Default for GrowthTrigger {
+    fn default() -> Self {
+        Self { factor: 1.5 }
+    }
+}
+
+impl VacuumTrigger for GrowthTrigger {
+    fn should_vacuum(&self, last_size: usize, current_size: usize) -> bool {
+        // precision loss only matters for maps far too big to fit in memory
+        #[allow(clippy::cast_precision_loss)]
+        let threshold = last_size as f64 * f64::from(self.factor);
+        #[allow(clippy::cast_precision_loss)]
+        let current = current_size as f64;
+        threshold < current
+    }
+}
+
+/// Vacuum at most once per `interval`, however much the map has grown. The
+/// first check always vacuums.
+#[derive(Debug)]
+pub struct TimeTrigger<C = SystemClock> {
+    interval: Duration,
+    clock: C,
+    start: Instant,
+    /// nanoseconds after `start` before which the next vacuum isn't allowed
+    next: AtomicU64,
+}
+
+#[cfg(feature = "std")]
+impl TimeTrigger {
+    /// Create a trigger which vacuums at most once per `interval` by the
+    /// system clock
+    pub fn new(interval: Duration) -> Self {
+        Self::with_clock(interval, SystemClock)
+    }
+}
+
+impl<C: Clock> TimeTrigger<C> {
+    /// Create a trigger which vacuums at most once per `interval` by `clock`
+    pub fn with_clock(interval: Duration, clock: C) -> Self {
+        Self {
+            interval,
+            start: clock.now(),
+            clock,
+            next: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<C: Clock> VacuumTrigger for TimeTrigger<C> {
+    fn should_vacuum(&self, _last_size: usize, _current_size: usize) -> bool {
+        let elapsed = nanos(self.clock.now().saturating_duration_since(self.start));
+        let next = self.next.load(Ordering::Relaxed);
+        elapsed >= next
+            && self
+                .next
+                .compare_exchange(
+                    next,
+                    elapsed.saturating_add(nanos(self.interval)),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// A map's custom [`VacuumTrigger`]. Clones of the map share it.
+#[derive(Clone)]
+pub struct Trigger(pub Arc<dyn VacuumTrigger + Send + Sync>);
+
+impl Debug for Trigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("VacuumTrigger")
+    }
+}
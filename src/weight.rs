@@ -0,0 +1,51 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::fmt::{self, Debug, Formatter};
+
+/// How much of a budget a value uses up, for maps created with
+/// [`ExpiringMap::with_max_weight`](crate::ExpiringMap::with_max_weight).
+/// This is usually its size in bytes.
+pub trait Weight {
+    /// The weight of this value
+    fn weight(&self) -> usize;
+}
+
+impl Weight for String {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> Weight for Vec<T> {
+    fn weight(&self) -> usize {
+        self.len() * size_of::<T>()
+    }
+}
+
+impl<T> Weight for Box<[T]> {
+    fn weight(&self) -> usize {
+        self.len() * size_of::<T>()
+    }
+}
+
+/// The weight limit of a map. `V: Weight` is only required to set a limit, so
+/// the function is captured here.
+pub struct Weigher<V> {
+    pub weigh: fn(&V) -> usize,
+    pub max: usize,
+}
+
+impl<V> Clone for Weigher<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V> Copy for Weigher<V> {}
+
+impl<V> Debug for Weigher<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Weigher")
+            .field("max", &self.max)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,153 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::{index::Deadline, time::Instant};
+
+/// how many bits of the tick count each level covers
+const LEVEL_BITS: u32 = 6;
+const SLOTS: usize = 1 << LEVEL_BITS;
+const LEVELS: u32 = 6;
+/// ticks past the current one that the wheel can hold without clamping
+const RANGE: u64 = 1 << (LEVEL_BITS * LEVELS);
+
+/// A hierarchical timing wheel of expiry deadlines.
+///
+/// Level 0 has a slot for each of the next 64 ticks, level 1 a slot for each
+/// of the next 64 groups of 64 ticks, and so on. Advancing the wheel only
+/// looks at occupied slots, and records cascade down a level each time their
+/// slot comes up, so each record is touched at most once per level.
+#[derive(Debug, Clone)]
+pub struct Wheel<K> {
+    start: Instant,
+    /// nanoseconds per tick
+    resolution: u128,
+    /// the last tick the wheel was advanced to
+    elapsed: u64,
+    levels: Vec<Level<K>>,
+    /// records whose tick has passed
+    due: Vec<Deadline<K>>,
+}
+
+#[derive(Debug, Clone)]
+struct Level<K> {
+    slots: Vec<Vec<Deadline<K>>>,
+    /// bit `n` is set if slot `n` has records
+    occupied: u64,
+}
+
+impl<K> Wheel<K> {
+    pub fn new(start: Instant, resolution: Duration) -> Self {
+        Self {
+            start,
+            resolution: resolution.as_nanos().max(1),
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+                    occupied: 0,
+                })
+                .collect(),
+            due: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, record: Deadline<K>) {
+        // round up, so a record is never due before its deadline
+        let nanos = record.at.saturating_duration_since(self.start).as_nanos();
+        let tick = u64::try_from(nanos.div_ceil(self.resolution)).unwrap_or(u64::MAX);
+        if tick <= self.elapsed {
+            self.due.push(record);
+            return;
+        }
+        // deadlines past the end of the wheel wait in its last slot, and are
+        // put back by the map when they turn out not to be due yet
+        let tick = tick.min(self.elapsed + RANGE - 1);
+        let level = Self::level_for(self.elapsed, tick);
+        let slot = Self::slot_for(tick, level);
+        self.levels[level].slots[slot].push(record);
+        self.levels[level].occupied |= 1 << slot;
+    }
+
+    /// remove and return a record whose deadline has passed as of `now`
+    pub fn pop_due(&mut self, now: Instant) -> Option<Deadline<K>> {
+        if self.due.is_empty() {
+            self.advance(now);
+        }
+        self.due.pop()
+    }
+
+    /// move the wheel forward to `now`, cascading every slot it passes
+    fn advance(&mut self, now: Instant) {
+        let nanos = now.saturating_duration_since(self.start).as_nanos();
+        let target = u64::try_from(nanos / self.resolution).unwrap_or(u64::MAX);
+        while let Some((level, slot, tick)) = self.next_occupied() {
+            if tick > target {
+                break;
+            }
+            self.elapsed = tick;
+            self.levels[level].occupied &= !(1 << slot);
+            for record in core::mem::take(&mut self.levels[level].slots[slot]) {
+                self.push(record);
+            }
+        }
+        self.elapsed = self.elapsed.max(target);
+    }
+
+    /// the level, slot, and starting tick of the soonest occupied slot
+    fn next_occupied(&self) -> Option<(usize, usize, u64)> {
+        self.levels.iter().enumerate().find_map(|(level, slots)| {
+            let shift = LEVEL_BITS * u32::try_from(level).ok()?;
+            let current = Self::slot_for(self.elapsed, level);
+            // rotate so the current slot is bit 0, to find the next one after it
+            let rotated = slots.occupied.rotate_right(u32::try_from(current).ok()?);
+            if rotated == 0 {
+                return None;
+            }
+            let slot = (current + rotated.trailing_zeros() as usize) % SLOTS;
+            let level_range = 1_u64.checked_shl(shift + LEVEL_BITS).unwrap_or(0);
+            let level_start = self.elapsed & !level_range.wrapping_sub(1);
+            let mut tick = level_start + ((slot as u64) << shift);
+            // the slot has come around already, so it's next time around
+            if tick <= self.elapsed {
+                tick = tick.wrapping_add(level_range);
+            }
+            Some((level, slot, tick))
+        })
+    }
+
+    /// the level for a record due at `tick`, from the highest group of bits
+    /// where it differs from `elapsed`
+    const fn level_for(elapsed: u64, tick: u64) -> usize {
+        let mut masked = (elapsed ^ tick) | (SLOTS as u64 - 1);
+        // clamped ticks can carry into bits past the top level
+        if masked >= RANGE {
+            masked = RANGE - 1;
+        }
+        (masked.ilog2() / LEVEL_BITS) as usize
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn slot_for(tick: u64, level: usize) -> usize {
+        ((tick >> (LEVEL_BITS * level as u32)) as usize) & (SLOTS - 1)
+    }
+
+    pub fn retain(&mut self, mut is_current: impl FnMut(&K, u64) -> bool) {
+        self.due.retain(|record| is_current(&record.key, record.id));
+        for level in &mut self.levels {
+            for (slot, records) in level.slots.iter_mut().enumerate() {
+                records.retain(|record| is_current(&record.key, record.id));
+                if records.is_empty() {
+                    level.occupied &= !(1 << slot);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.due.clear();
+        for level in &mut self.levels {
+            level.slots.iter_mut().for_each(Vec::clear);
+            level.occupied = 0;
+        }
+    }
+}